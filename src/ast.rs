@@ -0,0 +1,254 @@
+//! The document tree produced by [`Lexer::parse`](crate::Lexer::parse).
+
+use crate::slug::{SlugStyle, Slugger};
+
+/// A parsed Markdown document.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+impl Document {
+    /// Gives every heading a unique ID generated from its text, except those
+    /// whose text has no letters or digits.
+    pub fn assign_heading_ids(&mut self, style: SlugStyle) {
+        let mut slugger = Slugger::new(style);
+        assign_heading_ids(&mut self.blocks, &mut slugger);
+    }
+    /// Returns the top-level headings with a level between `min_depth` and
+    /// `max_depth`, in order.
+    pub fn headings(&self, min_depth: u8, max_depth: u8) -> Vec<TocEntry> {
+        self.blocks
+            .iter()
+            .filter_map(|block| match block {
+                Block::Heading { level, content, id }
+                    if (min_depth..=max_depth).contains(level) =>
+                {
+                    Some(TocEntry {
+                        level: *level,
+                        id: id.clone(),
+                        text: plain_text(content),
+                    })
+                }
+                _ => None,
+            })
+            .collect()
+    }
+    /// Fills every [`Block::TableOfContents`] with the headings returned by
+    /// [`Document::headings`].
+    pub fn fill_tables_of_contents(&mut self, min_depth: u8, max_depth: u8) {
+        let entries = self.headings(min_depth, max_depth);
+        fill_tables_of_contents(&mut self.blocks, &entries);
+    }
+    /// Returns the task list items of the document, in order.
+    pub fn tasks(&self) -> Vec<Task> {
+        let mut tasks = Vec::new();
+        collect_tasks(&self.blocks, &mut tasks);
+        tasks
+    }
+}
+
+/// A task list item such as `- [x] done`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Task {
+    pub checked: bool,
+    /// The plain text of the first paragraph of the item.
+    pub text: String,
+}
+
+/// A heading listed in a table of contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TocEntry {
+    pub level: u8,
+    pub id: Option<String>,
+    /// The plain text of the heading.
+    pub text: String,
+}
+
+fn fill_tables_of_contents(blocks: &mut [Block], entries: &[TocEntry]) {
+    for block in blocks {
+        match block {
+            Block::TableOfContents(toc) => *toc = entries.to_vec(),
+            Block::BlockQuote(blocks) | Block::FootnoteDefinition { blocks, .. } => {
+                fill_tables_of_contents(blocks, entries)
+            }
+            Block::List(list) => {
+                for item in &mut list.items {
+                    fill_tables_of_contents(&mut item.blocks, entries);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn assign_heading_ids(blocks: &mut [Block], slugger: &mut Slugger) {
+    for block in blocks {
+        match block {
+            Block::Heading { content, id, .. } => {
+                // Empty slugs are counted but not given as IDs.
+                let slug = slugger.slug(&plain_text(content));
+                *id = (!slug.is_empty()).then_some(slug);
+            }
+            Block::BlockQuote(blocks) | Block::FootnoteDefinition { blocks, .. } => {
+                assign_heading_ids(blocks, slugger)
+            }
+            Block::List(list) => {
+                for item in &mut list.items {
+                    assign_heading_ids(&mut item.blocks, slugger);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_tasks(blocks: &[Block], tasks: &mut Vec<Task>) {
+    for block in blocks {
+        match block {
+            Block::BlockQuote(blocks) | Block::FootnoteDefinition { blocks, .. } => {
+                collect_tasks(blocks, tasks)
+            }
+            Block::List(list) => {
+                for item in &list.items {
+                    if let Some(checked) = item.checked {
+                        let text = match item.blocks.first() {
+                            Some(Block::Paragraph(content)) => plain_text(content),
+                            _ => String::new(),
+                        };
+                        tasks.push(Task { checked, text });
+                    }
+                    collect_tasks(&item.blocks, tasks);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A block-level element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Heading {
+        level: u8,
+        content: Vec<Inline>,
+        /// Set by [`Document::assign_heading_ids`].
+        id: Option<String>,
+    },
+    Paragraph(Vec<Inline>),
+    BlockQuote(Vec<Block>),
+    List(List),
+    CodeBlock(CodeBlock),
+    Table(Table),
+    ThematicBreak,
+    /// A `[^label]: text` footnote definition, rendered at the end of the
+    /// document.
+    FootnoteDefinition {
+        label: String,
+        blocks: Vec<Block>,
+    },
+    /// A `[[TOC]]` placeholder, set by [`Document::fill_tables_of_contents`].
+    TableOfContents(Vec<TocEntry>),
+    /// Raw HTML lines, such as a `<details>` element.
+    Html(String),
+}
+
+/// An ordered or unordered list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct List {
+    pub ordered: bool,
+    /// The number of the first item of an ordered list.
+    pub start: u64,
+    /// No blank lines separate the items or their blocks, so paragraphs in
+    /// the items are rendered without `<p>`.
+    pub tight: bool,
+    pub items: Vec<ListItem>,
+}
+
+/// A single list item, which may hold any blocks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListItem {
+    /// `Some` for task list items, telling whether the box is checked.
+    pub checked: Option<bool>,
+    pub blocks: Vec<Block>,
+}
+
+/// A GFM pipe table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    /// The alignment of each column.
+    pub alignments: Vec<Alignment>,
+    /// The content of each header cell.
+    pub header: Vec<Vec<Inline>>,
+    /// The body rows, each with one cell per column.
+    pub rows: Vec<Vec<Vec<Inline>>>,
+}
+
+/// The alignment of a table column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// A fenced or indented code block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    /// The text following the opening fence, empty for indented code.
+    pub info: String,
+    /// The first word of the info string, e.g. `rust`.
+    pub language: Option<String>,
+    /// The `key=value` pairs following the language, e.g. `title="x"`.
+    pub attributes: Vec<(String, String)>,
+    /// The raw content of the block, each line ending with a newline.
+    pub literal: String,
+}
+
+/// An inline element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Code(String),
+    Emphasis(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Mark(Vec<Inline>),
+    Strikethrough(Vec<Inline>),
+    Link {
+        url: String,
+        title: Option<String>,
+        children: Vec<Inline>,
+    },
+    Image {
+        url: String,
+        title: Option<String>,
+        alt: String,
+    },
+    /// A `[^label]` reference to a footnote.
+    FootnoteReference(String),
+    /// A raw HTML tag, comment or declaration.
+    Html(String),
+    SoftBreak,
+    /// A line ending preceded by two spaces or a backslash.
+    HardBreak,
+}
+
+/// Returns the text of `inlines` without any formatting.
+pub fn plain_text(inlines: &[Inline]) -> String {
+    let mut text = String::new();
+    for inline in inlines {
+        match inline {
+            Inline::Text(t) | Inline::Code(t) => text.push_str(t),
+            Inline::Emphasis(children)
+            | Inline::Strong(children)
+            | Inline::Mark(children)
+            | Inline::Strikethrough(children)
+            | Inline::Link { children, .. } => text.push_str(&plain_text(children)),
+            Inline::Image { alt, .. } => text.push_str(alt),
+            Inline::FootnoteReference(_) | Inline::Html(_) => {}
+            Inline::SoftBreak | Inline::HardBreak => text.push(' '),
+        }
+    }
+    text
+}
@@ -0,0 +1,40 @@
+use crate::html::escape_html;
+
+/// Wraps an HTML fragment into a complete HTML document.
+pub struct Builder {
+    html: String,
+    title: String,
+    style: Option<String>,
+}
+
+impl Builder {
+    pub fn new(html: String) -> Builder {
+        Builder {
+            html,
+            title: String::from("Markdown"),
+            style: None,
+        }
+    }
+    /// Sets the content of the document `<title>`.
+    pub fn title(mut self, title: &str) -> Builder {
+        self.title = title.to_string();
+        self
+    }
+    /// Embeds `css` in a `<style>` element in the document head.
+    pub fn style(mut self, css: &str) -> Builder {
+        self.style = Some(css.to_string());
+        self
+    }
+    pub fn build(&self) -> String {
+        let style = match &self.style {
+            Some(css) => format!("<style>{}</style>", css),
+            None => String::new(),
+        };
+        format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{}</title>{}</head><body>{}</body></html>",
+            escape_html(&self.title),
+            style,
+            self.html
+        )
+    }
+}
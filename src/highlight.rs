@@ -0,0 +1,596 @@
+//! Server-side syntax highlighting for fenced code blocks.
+//!
+//! Code is split into tokens which are wrapped in `<span class="tok-…">`
+//! elements. The classes are `tok-keyword`, `tok-literal`, `tok-string`,
+//! `tok-number`, `tok-comment`, `tok-type`, `tok-function`, `tok-key` and
+//! `tok-variable`; [`Theme::css`] styles all of them.
+
+use crate::html::escape_html;
+
+/// A built-in stylesheet for highlighted code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Theme {
+    Light,
+    Dark,
+}
+
+impl Theme {
+    /// Returns the theme matching `name` (`light` or `dark`).
+    pub fn from_name(name: &str) -> Option<Theme> {
+        match name {
+            "light" => Some(Theme::Light),
+            "dark" => Some(Theme::Dark),
+            _ => None,
+        }
+    }
+    /// Returns the CSS for this theme.
+    pub fn css(&self) -> &'static str {
+        match self {
+            Theme::Light => THEME_LIGHT,
+            Theme::Dark => THEME_DARK,
+        }
+    }
+}
+
+const THEME_LIGHT: &str =
+    "pre code{display:block;overflow-x:auto;padding:1em;background:#f6f8fa;color:#24292e}\
+.tok-keyword{color:#d73a49}\
+.tok-literal,.tok-number{color:#005cc5}\
+.tok-string{color:#032f62}\
+.tok-comment{color:#6a737d;font-style:italic}\
+.tok-type{color:#6f42c1}\
+.tok-function{color:#6f42c1}\
+.tok-key{color:#22863a}\
+.tok-variable{color:#e36209}";
+
+const THEME_DARK: &str =
+    "pre code{display:block;overflow-x:auto;padding:1em;background:#0d1117;color:#c9d1d9}\
+.tok-keyword{color:#ff7b72}\
+.tok-literal,.tok-number{color:#79c0ff}\
+.tok-string{color:#a5d6ff}\
+.tok-comment{color:#8b949e;font-style:italic}\
+.tok-type{color:#ffa657}\
+.tok-function{color:#d2a8ff}\
+.tok-key{color:#7ee787}\
+.tok-variable{color:#ffa657}";
+
+/// The lexical rules of a highlighted language.
+struct Language {
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// Letters that may prefix a string literal, as in `f"…"` (any case).
+    string_prefixes: &'static [&'static str],
+    /// Keywords match regardless of case (SQL).
+    case_insensitive: bool,
+    /// Capitalized identifiers are highlighted as types.
+    capitalized_types: bool,
+    /// Words or strings followed by this character are keys.
+    key_separator: Option<char>,
+    /// The key separator must be followed by whitespace (YAML).
+    key_separator_spaced: bool,
+    /// Identifiers may contain `-`.
+    dashed_identifiers: bool,
+    /// `$name` and `${name}` are variables.
+    variables: bool,
+}
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+        "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "type",
+        "unsafe", "use", "where", "while",
+    ],
+    literals: &["true", "false"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+    string_prefixes: &[],
+    case_insensitive: false,
+    capitalized_types: true,
+    key_separator: None,
+    key_separator_spaced: false,
+    dashed_identifiers: false,
+    variables: false,
+};
+
+const PYTHON: Language = Language {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is",
+        "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while", "with",
+        "yield",
+    ],
+    literals: &["True", "False", "None"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    string_prefixes: &["r", "u", "f", "b", "br", "rb", "fr", "rf"],
+    case_insensitive: false,
+    capitalized_types: false,
+    key_separator: None,
+    key_separator_spaced: false,
+    dashed_identifiers: false,
+    variables: false,
+};
+
+const JS_KEYWORDS: [&str; 41] = [
+    "async",
+    "await",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "export",
+    "extends",
+    "finally",
+    "for",
+    "from",
+    "function",
+    "get",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "let",
+    "new",
+    "of",
+    "return",
+    "set",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+];
+
+const JAVASCRIPT: Language = Language {
+    keywords: &JS_KEYWORDS,
+    literals: &["true", "false", "null", "undefined", "NaN"],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+    string_prefixes: &[],
+    case_insensitive: false,
+    capitalized_types: true,
+    key_separator: None,
+    key_separator_spaced: false,
+    dashed_identifiers: false,
+    variables: false,
+};
+
+const TYPESCRIPT: Language = Language {
+    keywords: &join::<60>(
+        &JS_KEYWORDS,
+        &[
+            "abstract",
+            "any",
+            "as",
+            "boolean",
+            "declare",
+            "enum",
+            "implements",
+            "interface",
+            "keyof",
+            "namespace",
+            "never",
+            "number",
+            "private",
+            "protected",
+            "public",
+            "readonly",
+            "string",
+            "type",
+            "unknown",
+        ],
+    ),
+    ..JAVASCRIPT
+};
+
+/// Joins two keyword lists, `N` being their total length.
+const fn join<const N: usize>(
+    first: &[&'static str],
+    second: &[&'static str],
+) -> [&'static str; N] {
+    assert!(first.len() + second.len() == N);
+    let mut joined = [""; N];
+    let mut i = 0;
+    while i < N {
+        joined[i] = if i < first.len() {
+            first[i]
+        } else {
+            second[i - first.len()]
+        };
+        i += 1;
+    }
+    joined
+}
+
+const SHELL: Language = Language {
+    keywords: &[
+        "if", "then", "else", "elif", "fi", "case", "esac", "for", "while", "until", "do", "done",
+        "in", "function", "return", "local", "export", "readonly", "exit", "source", "alias",
+        "unset", "shift",
+    ],
+    literals: &[],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    string_prefixes: &[],
+    case_insensitive: false,
+    capitalized_types: false,
+    key_separator: None,
+    key_separator_spaced: false,
+    dashed_identifiers: true,
+    variables: true,
+};
+
+const JSON: Language = Language {
+    keywords: &[],
+    literals: &["true", "false", "null"],
+    line_comments: &[],
+    block_comment: None,
+    quotes: &['"'],
+    string_prefixes: &[],
+    case_insensitive: false,
+    capitalized_types: false,
+    key_separator: Some(':'),
+    key_separator_spaced: false,
+    dashed_identifiers: false,
+    variables: false,
+};
+
+const YAML: Language = Language {
+    keywords: &[],
+    literals: &["true", "false", "null", "yes", "no", "on", "off", "~"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    string_prefixes: &[],
+    case_insensitive: false,
+    capitalized_types: false,
+    key_separator: Some(':'),
+    key_separator_spaced: true,
+    dashed_identifiers: true,
+    variables: false,
+};
+
+const TOML: Language = Language {
+    keywords: &[],
+    literals: &["true", "false"],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+    string_prefixes: &[],
+    case_insensitive: false,
+    capitalized_types: false,
+    key_separator: Some('='),
+    key_separator_spaced: false,
+    dashed_identifiers: true,
+    variables: false,
+};
+
+const SQL: Language = Language {
+    keywords: &[
+        "add",
+        "all",
+        "alter",
+        "and",
+        "as",
+        "asc",
+        "begin",
+        "between",
+        "by",
+        "case",
+        "check",
+        "commit",
+        "create",
+        "default",
+        "delete",
+        "desc",
+        "distinct",
+        "drop",
+        "else",
+        "end",
+        "exists",
+        "foreign",
+        "from",
+        "full",
+        "group",
+        "having",
+        "in",
+        "index",
+        "inner",
+        "insert",
+        "into",
+        "is",
+        "join",
+        "key",
+        "left",
+        "like",
+        "limit",
+        "not",
+        "offset",
+        "on",
+        "or",
+        "order",
+        "outer",
+        "primary",
+        "references",
+        "returning",
+        "right",
+        "rollback",
+        "select",
+        "set",
+        "table",
+        "then",
+        "union",
+        "unique",
+        "update",
+        "values",
+        "view",
+        "when",
+        "where",
+        "with",
+    ],
+    literals: &["null", "true", "false"],
+    line_comments: &["--"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['\'', '"'],
+    string_prefixes: &["n", "e", "x", "b"],
+    case_insensitive: true,
+    capitalized_types: false,
+    key_separator: None,
+    key_separator_spaced: false,
+    dashed_identifiers: false,
+    variables: false,
+};
+
+fn language(name: &str) -> Option<&'static Language> {
+    match name.to_ascii_lowercase().as_str() {
+        "rust" | "rs" => Some(&RUST),
+        "python" | "py" => Some(&PYTHON),
+        "javascript" | "js" | "jsx" | "mjs" => Some(&JAVASCRIPT),
+        "typescript" | "ts" | "tsx" => Some(&TYPESCRIPT),
+        "shell" | "sh" | "bash" | "zsh" | "console" => Some(&SHELL),
+        "json" => Some(&JSON),
+        "yaml" | "yml" => Some(&YAML),
+        "toml" => Some(&TOML),
+        "sql" => Some(&SQL),
+        _ => None,
+    }
+}
+
+/// Highlights `code` written in `language`, returning escaped HTML.
+///
+/// Returns `None` if the language is not supported.
+pub fn highlight(language_name: &str, code: &str) -> Option<String> {
+    let language = language(language_name)?;
+    let mut html = String::with_capacity(code.len() * 2);
+    let mut plain = String::new();
+    let mut i = 0;
+    while i < code.len() {
+        let rest = &code[i..];
+        let previous = code[..i].chars().next_back();
+        if let Some((class, len)) = next_token(language, rest, previous) {
+            if class.is_empty() {
+                plain.push_str(&rest[..len]);
+            } else {
+                html.push_str(&escape_html(&plain));
+                plain.clear();
+                html.push_str(&format!(
+                    "<span class=\"tok-{}\">{}</span>",
+                    class,
+                    escape_html(&rest[..len])
+                ));
+            }
+            i += len;
+        } else {
+            let c = rest.chars().next().unwrap();
+            plain.push(c);
+            i += c.len_utf8();
+        }
+    }
+    html.push_str(&escape_html(&plain));
+    Some(html)
+}
+
+/// Returns the class and length of the token starting at `text`, if any.
+///
+/// Words that are not highlighted are returned with an empty class so they
+/// are skipped as a whole.
+fn next_token(
+    language: &Language,
+    text: &str,
+    previous: Option<char>,
+) -> Option<(&'static str, usize)> {
+    let c = text.chars().next()?;
+    let after_word = previous.is_some_and(|p| is_identifier_char(language, p));
+    for prefix in language.line_comments {
+        // `#` only starts a comment at the start of a word, as in `a#b`, and
+        // not in shell variables such as `$#`.
+        if text.starts_with(prefix) && !(prefix == &"#" && (after_word || previous == Some('$'))) {
+            return Some(("comment", text.find('\n').unwrap_or(text.len())));
+        }
+    }
+    if let Some((open, close)) = language.block_comment {
+        if let Some(body) = text.strip_prefix(open) {
+            let len = body
+                .find(close)
+                .map_or(text.len(), |end| open.len() + end + close.len());
+            return Some(("comment", len));
+        }
+    }
+    // A quote right after a word is an apostrophe, as in `don't`. String
+    // prefixes such as `f` in `f"…"` are read along with their word.
+    if language.quotes.contains(&c) && !(c == '\'' && after_word) {
+        let len = string_length(text, c);
+        let class = if is_key(language, &text[len..]) {
+            "key"
+        } else {
+            "string"
+        };
+        return Some((class, len));
+    }
+    if language.variables && c == '$' {
+        let rest = &text[1..];
+        let len = if rest.starts_with('{') {
+            rest.find('}').map_or(text.len(), |end| end + 2)
+        } else if rest.starts_with(['#', '?', '$', '!', '@', '*', '-']) {
+            // Special parameters such as `$#` and `$?`.
+            2
+        } else {
+            1 + rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len())
+        };
+        return if len > 1 {
+            Some(("variable", len))
+        } else {
+            None
+        };
+    }
+    if after_word {
+        return None;
+    }
+    if c.is_ascii_digit() {
+        let len = text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+            .unwrap_or(text.len());
+        return Some(("number", len));
+    }
+    if c.is_alphabetic() || c == '_' || (c == '~' && language.literals.contains(&"~")) {
+        let len = text
+            .find(|c: char| !is_identifier_char(language, c))
+            .unwrap_or(text.len())
+            .max(c.len_utf8());
+        let word = &text[..len];
+        let rest = &text[len..];
+        if let Some(quote) = rest.chars().next().filter(|c| language.quotes.contains(c)) {
+            let prefixed = language
+                .string_prefixes
+                .iter()
+                .any(|prefix| prefix.eq_ignore_ascii_case(word));
+            if prefixed {
+                return Some(("string", len + string_length(rest, quote)));
+            }
+        }
+        return Some((classify_word(language, word, rest), len));
+    }
+    None
+}
+
+fn classify_word(language: &Language, word: &str, rest: &str) -> &'static str {
+    let matches = |list: &[&str]| {
+        if language.case_insensitive {
+            list.iter().any(|w| w.eq_ignore_ascii_case(word))
+        } else {
+            list.contains(&word)
+        }
+    };
+    if is_key(language, rest) {
+        "key"
+    } else if matches(language.keywords) {
+        "keyword"
+    } else if matches(language.literals) {
+        "literal"
+    } else if language.capitalized_types && word.starts_with(|c: char| c.is_uppercase()) {
+        "type"
+    } else if language.key_separator.is_none() && rest.starts_with('(') {
+        "function"
+    } else {
+        ""
+    }
+}
+
+fn is_identifier_char(language: &Language, c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || (language.dashed_identifiers && c == '-')
+}
+
+/// Returns true if `rest`, the text after a word or string, makes it a key.
+fn is_key(language: &Language, rest: &str) -> bool {
+    let Some(separator) = language.key_separator else {
+        return false;
+    };
+    let rest = rest.trim_start_matches([' ', '\t']);
+    match rest.strip_prefix(separator) {
+        Some(after) => {
+            !language.key_separator_spaced
+                || after.is_empty()
+                || after.starts_with(char::is_whitespace)
+        }
+        None => false,
+    }
+}
+
+/// Returns the length of the string literal starting at `text`.
+fn string_length(text: &str, quote: char) -> usize {
+    let triple: String = std::iter::repeat_n(quote, 3).collect();
+    if quote != '`' && text.starts_with(&triple) {
+        return text[3..].find(&triple).map_or(text.len(), |end| end + 6);
+    }
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == quote {
+            return i + 1;
+        } else if c == '\n' && quote != '`' {
+            return i;
+        }
+    }
+    text.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn highlights_special_shell_variables() {
+        assert_eq!(
+            highlight("sh", "echo $# $HOME # done").unwrap(),
+            "echo <span class=\"tok-variable\">$#</span> \
+             <span class=\"tok-variable\">$HOME</span> \
+             <span class=\"tok-comment\"># done</span>"
+        );
+    }
+
+    #[test]
+    fn typescript_extends_javascript_keywords() {
+        assert!(TYPESCRIPT.keywords.contains(&"yield"));
+        assert!(TYPESCRIPT.keywords.contains(&"interface"));
+        assert!(!JAVASCRIPT.keywords.contains(&"interface"));
+    }
+
+    #[test]
+    fn highlights_prefixed_strings() {
+        assert_eq!(
+            highlight("py", "x = f'{a}' + rb''").unwrap(),
+            "x = <span class=\"tok-string\">f'{a}'</span> + <span class=\"tok-string\">rb''</span>"
+        );
+        assert_eq!(
+            highlight("sql", "N'ab'").unwrap(),
+            "<span class=\"tok-string\">N'ab'</span>"
+        );
+    }
+}
@@ -0,0 +1,641 @@
+use crate::ast::{Alignment, Block, CodeBlock, Document, Inline, List, Table, TocEntry};
+use crate::highlight::highlight;
+use crate::options::Options;
+use crate::sanitize::{close_elements, is_safe_url, HtmlMode};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+
+/// A single HTML element whose children are already rendered to HTML.
+///
+/// Attributes are written in the order they are given, those with an empty
+/// value as bare names.
+#[derive(Debug, Clone)]
+pub struct HTMLElemnt {
+    pub tag: String,
+    pub attrs: Option<Vec<(String, String)>>,
+    pub childrens: String,
+}
+
+/// A list element (`ul` or `ol`) made of `li` elements.
+pub struct HTMLElemntList {
+    pub elemnts: Vec<HTMLElemnt>,
+    pub list_type: String,
+    pub attrs: Option<Vec<(String, String)>>,
+}
+
+impl HTMLElemntList {
+    pub fn new(list_type: String) -> HTMLElemntList {
+        HTMLElemntList {
+            elemnts: Vec::new(),
+            list_type,
+            attrs: None,
+        }
+    }
+    pub fn add(&mut self, elemnt: HTMLElemnt) {
+        self.elemnts.push(elemnt);
+    }
+    pub fn clear(&mut self) {
+        self.elemnts.clear();
+    }
+    pub fn to_html(&self) -> String {
+        let childrens = self.elemnts.iter().map(HTMLElemnt::to_html).collect();
+        HTMLElemnt::new(self.list_type.clone(), self.attrs.clone(), childrens).to_html()
+    }
+}
+
+impl HTMLElemnt {
+    pub fn new(tag: String, attrs: Option<Vec<(String, String)>>, childrens: String) -> HTMLElemnt {
+        HTMLElemnt {
+            tag,
+            attrs,
+            childrens,
+        }
+    }
+    pub fn to_html(&self) -> String {
+        let mut html = format!("<{}", self.tag);
+        if let Some(attrs) = &self.attrs {
+            for (key, value) in attrs.iter() {
+                if value.is_empty() {
+                    html.push_str(&format!(" {}", key));
+                } else {
+                    html.push_str(&format!(" {}=\"{}\"", key, escape_html(value)));
+                }
+            }
+        }
+        if self.tag == "img" {
+            html.push_str(&format!(" alt=\"{}\"/>", self.childrens));
+        } else if self.tag == "hr" || self.tag == "input" {
+            html.push('>');
+        } else {
+            html.push_str(&format!(">{}</{}>", self.childrens, self.tag));
+        }
+        html
+    }
+}
+
+/// Renders a [`Document`] tree to HTML.
+pub struct Renderer<'a> {
+    options: &'a Options,
+    footnotes: RefCell<Footnotes>,
+    /// The sanitized raw HTML elements that are not closed yet.
+    open_elements: RefCell<Vec<String>>,
+}
+
+/// Footnote numbering, assigned while rendering in order of first reference.
+#[derive(Default)]
+struct Footnotes {
+    /// Lowercased labels of the defined footnotes.
+    defined: HashSet<String>,
+    /// Lowercased labels of referenced footnotes with their reference count.
+    referenced: Vec<(String, usize)>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(options: &'a Options) -> Renderer<'a> {
+        Renderer {
+            options,
+            footnotes: RefCell::new(Footnotes::default()),
+            open_elements: RefCell::new(Vec::new()),
+        }
+    }
+    /// Renders `document`, followed by a `<section class="footnotes">` if
+    /// it references footnotes.
+    pub fn render(&self, document: &Document) -> String {
+        let mut definitions = HashMap::new();
+        collect_footnote_definitions(&document.blocks, &mut definitions);
+        *self.footnotes.borrow_mut() = Footnotes {
+            defined: definitions.keys().cloned().collect(),
+            referenced: Vec::new(),
+        };
+        self.open_elements.borrow_mut().clear();
+        let mut html = self.render_blocks(&document.blocks);
+        let footnotes = self.render_footnotes(&definitions);
+        if !footnotes.is_empty() {
+            html.push('\n');
+            html.push_str(&footnotes);
+        }
+        html
+    }
+    fn render_blocks(&self, blocks: &[Block]) -> String {
+        blocks
+            .iter()
+            .filter(|block| !matches!(block, Block::FootnoteDefinition { .. }))
+            .map(|block| self.render_block(block))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+    fn render_block(&self, block: &Block) -> String {
+        match block {
+            Block::Heading { level, content, id } => self.render_heading(*level, content, id),
+            Block::Paragraph(content) => element("p", None, self.render_leaf(content)),
+            Block::BlockQuote(blocks) => {
+                let childrens = self.render_blocks(blocks) + &self.close_open_elements();
+                element("blockquote", None, childrens)
+            }
+            Block::List(list) => self.render_list(list),
+            Block::CodeBlock(code) => self.render_code(code),
+            Block::Table(table) => self.render_table(table),
+            Block::ThematicBreak => element("hr", None, String::new()),
+            Block::TableOfContents(entries) => self.render_toc(entries),
+            // Definitions are rendered by `render_footnotes`.
+            Block::FootnoteDefinition { .. } => String::new(),
+            Block::Html(html) => match self.html_mode() {
+                HtmlMode::Escape => element("p", None, escape_html(html)),
+                _ => self.render_html(html) + &self.close_open_elements(),
+            },
+        }
+    }
+    fn render_heading(&self, level: u8, content: &[Inline], id: &Option<String>) -> String {
+        let mut childrens = self.render_leaf(content);
+        let mut attrs = None;
+        if let Some(id) = id {
+            if self.options.heading_anchors {
+                let href = format!("#{}", id);
+                let anchor = element(
+                    "a",
+                    Some(attributes(&[
+                        ("class", "anchor"),
+                        ("href", &href),
+                        ("aria-hidden", "true"),
+                    ])),
+                    String::from("#"),
+                );
+                childrens = anchor + &childrens;
+            }
+            attrs = Some(attributes(&[("id", id)]));
+        }
+        element(&format!("h{}", level), attrs, childrens)
+    }
+    fn render_toc(&self, entries: &[TocEntry]) -> String {
+        let attrs = Some(attributes(&[("class", "toc")]));
+        element("nav", attrs, self.render_toc_list(entries))
+    }
+    /// Renders entries as an `<ol>`, nesting deeper headings under the
+    /// shallower heading before them.
+    fn render_toc_list(&self, entries: &[TocEntry]) -> String {
+        let mut list = HTMLElemntList::new(String::from("ol"));
+        let Some(base) = entries.iter().map(|entry| entry.level).min() else {
+            return String::new();
+        };
+        let mut i = 0;
+        while i < entries.len() {
+            let entry = &entries[i];
+            let mut end = i + 1;
+            while end < entries.len() && entries[end].level > base {
+                end += 1;
+            }
+            let childrens = if entry.level > base {
+                // Headings deeper than the first one have no parent entry.
+                self.render_toc_list(&entries[i..end])
+            } else {
+                let text = escape_html(&entry.text);
+                let mut childrens = match &entry.id {
+                    Some(id) => element(
+                        "a",
+                        Some(attributes(&[("href", &format!("#{}", id))])),
+                        text,
+                    ),
+                    None => text,
+                };
+                childrens.push_str(&self.render_toc_list(&entries[i + 1..end]));
+                childrens
+            };
+            list.add(HTMLElemnt::new(String::from("li"), None, childrens));
+            i = end;
+        }
+        list.to_html()
+    }
+    fn render_list(&self, list: &List) -> String {
+        let mut html = HTMLElemntList::new(String::from(if list.ordered { "ol" } else { "ul" }));
+        if list.ordered && list.start != 1 {
+            html.attrs = Some(vec![(String::from("start"), list.start.to_string())]);
+        }
+        for item in &list.items {
+            let mut childrens = if list.tight {
+                self.render_item_blocks(&item.blocks)
+            } else {
+                self.render_blocks(&item.blocks)
+            };
+            childrens.push_str(&self.close_open_elements());
+            let mut attrs = None;
+            if let Some(checked) = item.checked {
+                let mut checkbox = vec![
+                    (String::from("type"), String::from("checkbox")),
+                    (String::from("disabled"), String::new()),
+                ];
+                if checked {
+                    checkbox.push((String::from("checked"), String::new()));
+                }
+                let checkbox = element("input", Some(checkbox), String::new());
+                // The checkbox goes inside the first paragraph of loose items.
+                childrens = match childrens.strip_prefix("<p>") {
+                    Some(rest) => format!("<p>{} {}", checkbox, rest),
+                    None => format!("{} {}", checkbox, childrens),
+                };
+                attrs = Some(vec![(
+                    String::from("class"),
+                    String::from("task-list-item"),
+                )]);
+            }
+            html.add(HTMLElemnt::new(String::from("li"), attrs, childrens));
+        }
+        html.to_html()
+    }
+    /// Renders the blocks of a tight list item, leaving paragraphs unwrapped.
+    fn render_item_blocks(&self, blocks: &[Block]) -> String {
+        blocks
+            .iter()
+            .filter(|block| !matches!(block, Block::FootnoteDefinition { .. }))
+            .map(|block| match block {
+                Block::Paragraph(content) => self.render_leaf(content),
+                _ => self.render_block(block),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+    fn render_code(&self, code: &CodeBlock) -> String {
+        let mut attrs = Vec::new();
+        if let Some(language) = &code.language {
+            attrs.push((String::from("class"), format!("language-{}", language)));
+        }
+        for (key, value) in &code.attributes {
+            attrs.push((format!("data-{}", key), value.clone()));
+        }
+        let attrs = if attrs.is_empty() { None } else { Some(attrs) };
+        let highlighted = match &code.language {
+            Some(language) if self.options.highlight => highlight(language, &code.literal),
+            _ => None,
+        };
+        let childrens = highlighted.unwrap_or_else(|| escape_html(&code.literal));
+        element("pre", None, element("code", attrs, childrens))
+    }
+    fn render_table(&self, table: &Table) -> String {
+        let header = self.render_table_row(&table.header, &table.alignments, "th");
+        let mut html = element("thead", None, header);
+        if !table.rows.is_empty() {
+            let rows = table
+                .rows
+                .iter()
+                .map(|row| self.render_table_row(row, &table.alignments, "td"))
+                .collect::<Vec<String>>()
+                .join("\n");
+            html.push('\n');
+            html.push_str(&element("tbody", None, rows));
+        }
+        element("table", None, html)
+    }
+    fn render_table_row(
+        &self,
+        cells: &[Vec<Inline>],
+        alignments: &[Alignment],
+        tag: &str,
+    ) -> String {
+        let cells: String = cells
+            .iter()
+            .zip(alignments)
+            .map(|(cell, alignment)| {
+                let align = match alignment {
+                    Alignment::None => None,
+                    Alignment::Left => Some("left"),
+                    Alignment::Center => Some("center"),
+                    Alignment::Right => Some("right"),
+                };
+                let attrs = align.map(|align| attributes(&[("align", align)]));
+                element(tag, attrs, self.render_leaf(cell))
+            })
+            .collect();
+        element("tr", None, cells)
+    }
+    /// Renders the content of a paragraph, heading or table cell, closing
+    /// the raw HTML elements it leaves open.
+    fn render_leaf(&self, inlines: &[Inline]) -> String {
+        self.render_inlines(inlines) + &self.close_open_elements()
+    }
+    /// Closes the sanitized raw HTML elements that are still open, so that
+    /// they don't spill over into the following blocks.
+    fn close_open_elements(&self) -> String {
+        close_elements(&mut self.open_elements.borrow_mut())
+    }
+    fn render_inlines(&self, inlines: &[Inline]) -> String {
+        inlines
+            .iter()
+            .map(|inline| self.render_inline(inline))
+            .collect()
+    }
+    fn render_inline(&self, inline: &Inline) -> String {
+        match inline {
+            Inline::Text(text) => escape_html(text),
+            Inline::Code(code) => element("code", None, escape_html(code)),
+            Inline::Emphasis(children) => element("em", None, self.render_inlines(children)),
+            Inline::Strong(children) => element("strong", None, self.render_inlines(children)),
+            Inline::Mark(children) => element("mark", None, self.render_inlines(children)),
+            Inline::Strikethrough(children) => element("del", None, self.render_inlines(children)),
+            Inline::Link {
+                url,
+                title,
+                children,
+            } => element(
+                "a",
+                Some(self.link_attributes("href", url, title)),
+                self.render_inlines(children),
+            ),
+            Inline::Image { url, title, alt } => element(
+                "img",
+                Some(self.link_attributes("src", url, title)),
+                escape_html(alt),
+            ),
+            Inline::FootnoteReference(label) => self.render_footnote_reference(label),
+            Inline::Html(html) => self.render_html(html),
+            Inline::SoftBreak if self.options.hard_breaks => String::from("<br>\n"),
+            Inline::SoftBreak => String::from("\n"),
+            Inline::HardBreak => String::from("<br>\n"),
+        }
+    }
+    /// Returns how raw HTML is rendered: as set by [`Options::html`], except
+    /// that it is never passed through in safe mode.
+    fn html_mode(&self) -> HtmlMode {
+        match self.options.html {
+            HtmlMode::PassThrough if self.options.safe => HtmlMode::Sanitize,
+            mode => mode,
+        }
+    }
+    /// Renders raw HTML according to [`Renderer::html_mode`].
+    fn render_html(&self, html: &str) -> String {
+        match self.html_mode() {
+            HtmlMode::PassThrough => html.to_string(),
+            HtmlMode::Escape => escape_html(html),
+            HtmlMode::Sanitize => self.options.sanitizer.sanitize_fragment(
+                html,
+                &self.options.allowed_schemes,
+                &mut self.open_elements.borrow_mut(),
+            ),
+        }
+    }
+    /// The attributes of a link or an image: the url, then the title if any.
+    ///
+    /// In safe mode, urls with a scheme that is not allowed are left out.
+    fn link_attributes(
+        &self,
+        name: &str,
+        url: &str,
+        title: &Option<String>,
+    ) -> Vec<(String, String)> {
+        let mut attrs = Vec::new();
+        let image = name == "src";
+        if !self.options.safe || is_safe_url(url, &self.options.allowed_schemes, image) {
+            attrs.push((name.to_string(), url.to_string()));
+        }
+        if let Some(title) = title {
+            attrs.push((String::from("title"), title.clone()));
+        }
+        attrs
+    }
+    /// Renders a numbered reference, or the literal text if the footnote is
+    /// not defined.
+    fn render_footnote_reference(&self, label: &str) -> String {
+        let key = label.to_lowercase();
+        let mut footnotes = self.footnotes.borrow_mut();
+        if !footnotes.defined.contains(&key) {
+            return format!("[^{}]", escape_html(label));
+        }
+        let index = match footnotes.referenced.iter().position(|(k, _)| *k == key) {
+            Some(index) => index,
+            None => {
+                footnotes.referenced.push((key, 0));
+                footnotes.referenced.len() - 1
+            }
+        };
+        footnotes.referenced[index].1 += 1;
+        let number = index + 1;
+        let count = footnotes.referenced[index].1;
+        let link = element(
+            "a",
+            Some(attributes(&[
+                ("href", &format!("#fn-{}", number)),
+                ("id", &footnote_reference_id(number, count)),
+            ])),
+            number.to_string(),
+        );
+        element("sup", Some(attributes(&[("class", "footnote-ref")])), link)
+    }
+    /// Renders the referenced footnotes in order, each with a back-link to
+    /// every one of its references.
+    fn render_footnotes(&self, definitions: &HashMap<String, &[Block]>) -> String {
+        let mut list = HTMLElemntList::new(String::from("ol"));
+        let mut index = 0;
+        // Footnotes may reference further footnotes, growing the list.
+        while index < self.footnotes.borrow().referenced.len() {
+            let key = self.footnotes.borrow().referenced[index].0.clone();
+            let mut childrens = self.render_blocks(definitions[&key]);
+            let number = index + 1;
+            let count = self.footnotes.borrow().referenced[index].1;
+            let backrefs = (1..=count)
+                .map(|n| {
+                    let href = format!("#{}", footnote_reference_id(number, n));
+                    let mut text = String::from("↩");
+                    if n > 1 {
+                        text.push_str(&element("sup", None, n.to_string()));
+                    }
+                    let attrs = attributes(&[("href", &href), ("class", "footnote-backref")]);
+                    element("a", Some(attrs), text)
+                })
+                .collect::<Vec<String>>()
+                .join(" ");
+            match childrens.strip_suffix("</p>") {
+                Some(rest) => childrens = format!("{} {}</p>", rest, backrefs),
+                None => {
+                    childrens.push('\n');
+                    childrens.push_str(&backrefs);
+                }
+            }
+            let attrs = Some(attributes(&[("id", &format!("fn-{}", number))]));
+            list.add(HTMLElemnt::new(String::from("li"), attrs, childrens));
+            index += 1;
+        }
+        if list.elemnts.is_empty() {
+            return String::new();
+        }
+        let attrs = Some(attributes(&[("class", "footnotes")]));
+        element("section", attrs, list.to_html())
+    }
+}
+
+fn footnote_reference_id(number: usize, count: usize) -> String {
+    if count == 1 {
+        format!("fnref-{}", number)
+    } else {
+        format!("fnref-{}-{}", number, count)
+    }
+}
+
+/// Collects footnote definitions by lowercased label; the first one wins.
+fn collect_footnote_definitions<'d>(
+    blocks: &'d [Block],
+    definitions: &mut HashMap<String, &'d [Block]>,
+) {
+    for block in blocks {
+        match block {
+            Block::FootnoteDefinition { label, blocks } => {
+                definitions.entry(label.to_lowercase()).or_insert(blocks);
+                collect_footnote_definitions(blocks, definitions);
+            }
+            Block::BlockQuote(blocks) => collect_footnote_definitions(blocks, definitions),
+            Block::List(list) => {
+                for item in &list.items {
+                    collect_footnote_definitions(&item.blocks, definitions);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn element(tag: &str, attrs: Option<Vec<(String, String)>>, childrens: String) -> String {
+    HTMLElemnt::new(tag.to_string(), attrs, childrens).to_html()
+}
+
+fn attributes(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+/// Escapes the characters that have a special meaning in HTML text.
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{parse, to_html, Options, Task};
+
+    fn safe() -> Options {
+        Options {
+            safe: true,
+            ..Options::default()
+        }
+    }
+
+    #[test]
+    fn closes_raw_html_at_the_end_of_its_block() {
+        assert_eq!(
+            to_html("- <div>a\n- b\n\nc", &safe()),
+            "<ul><li><div>a</div></li><li>b</li></ul>\n<p>c</p>"
+        );
+        assert_eq!(
+            to_html("> <b>x\n\ny", &safe()),
+            "<blockquote><p><b>x</b></p></blockquote>\n<p>y</p>"
+        );
+    }
+
+    #[test]
+    fn renders_task_list_items() {
+        assert_eq!(
+            to_html(
+                "- [ ] todo\n- [x] done\n\n  more\n- plain",
+                &Options::default()
+            ),
+            "<ul><li class=\"task-list-item\"><p><input type=\"checkbox\" disabled> todo</p></li>\
+             <li class=\"task-list-item\"><p><input type=\"checkbox\" disabled checked> done</p>\n\
+             <p>more</p></li><li><p>plain</p></li></ul>"
+        );
+        let task = |checked, text: &str| Task {
+            checked,
+            text: text.to_string(),
+        };
+        assert_eq!(
+            parse("- [ ] todo\n- [X] *done*\n  - [x] nested").tasks(),
+            vec![
+                task(false, "todo"),
+                task(true, "done"),
+                task(true, "nested")
+            ]
+        );
+    }
+
+    #[test]
+    fn nests_table_of_contents_entries() {
+        let html = to_html(
+            "[[TOC]]\n\n# A\n### B\n## C\n#### D\n## E",
+            &Options::default(),
+        );
+        assert!(html.starts_with(
+            "<nav class=\"toc\"><ol><li><a href=\"#a\">A</a><ol>\
+             <li><ol><li><a href=\"#b\">B</a></li></ol></li>\
+             <li><a href=\"#c\">C</a><ol><li><a href=\"#d\">D</a></li></ol></li>\
+             <li><a href=\"#e\">E</a></li></ol></li></ol></nav>\n"
+        ));
+        let options = Options {
+            toc_min_depth: 2,
+            toc_max_depth: 2,
+            ..Options::default()
+        };
+        let html = to_html("[[TOC]]\n\n# A\n## B\n### C", &options);
+        assert!(
+            html.starts_with("<nav class=\"toc\"><ol><li><a href=\"#b\">B</a></li></ol></nav>\n")
+        );
+    }
+
+    #[test]
+    fn numbers_footnotes_in_order_of_reference() {
+        assert_eq!(
+            to_html("a[^x] b[^y] c[^x] d[^z]\n\n[^y]: Y\n[^x]: X", &Options::default()),
+            "<p>a<sup class=\"footnote-ref\"><a href=\"#fn-1\" id=\"fnref-1\">1</a></sup> \
+             b<sup class=\"footnote-ref\"><a href=\"#fn-2\" id=\"fnref-2\">2</a></sup> \
+             c<sup class=\"footnote-ref\"><a href=\"#fn-1\" id=\"fnref-1-2\">1</a></sup> d[^z]</p>\n\
+             <section class=\"footnotes\"><ol><li id=\"fn-1\"><p>X \
+             <a href=\"#fnref-1\" class=\"footnote-backref\">↩</a> \
+             <a href=\"#fnref-1-2\" class=\"footnote-backref\">↩<sup>2</sup></a></p></li>\
+             <li id=\"fn-2\"><p>Y <a href=\"#fnref-2\" class=\"footnote-backref\">↩</a></p></li>\
+             </ol></section>"
+        );
+    }
+
+    #[test]
+    fn leaves_out_unsafe_urls_in_safe_mode() {
+        let markdown = "[a](javascript:alert(1)) ![i](javascript:x) [b](https://e.com)";
+        assert_eq!(
+            to_html(markdown, &safe()),
+            "<p><a>a</a> <img alt=\"i\"/> <a href=\"https://e.com\">b</a></p>"
+        );
+        assert!(to_html(markdown, &Options::default()).contains("href=\"javascript:alert(1)\""));
+    }
+
+    #[test]
+    fn renders_soft_breaks_as_hard_breaks_when_enabled() {
+        let options = Options {
+            hard_breaks: true,
+            ..Options::default()
+        };
+        assert_eq!(to_html("a\nb  \nc", &options), "<p>a<br>\nb<br>\nc</p>");
+        assert_eq!(to_html("a\nb", &Options::default()), "<p>a\nb</p>");
+    }
+
+    #[test]
+    fn inserts_heading_anchors_when_enabled() {
+        let options = Options {
+            heading_anchors: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            to_html("# Intro\n## Intro", &options),
+            "<h1 id=\"intro\"><a class=\"anchor\" href=\"#intro\" aria-hidden=\"true\">#</a>Intro</h1>\n\
+             <h2 id=\"intro-1\"><a class=\"anchor\" href=\"#intro-1\" aria-hidden=\"true\">#</a>Intro</h2>"
+        );
+        assert_eq!(
+            to_html("# Intro", &Options::default()),
+            "<h1 id=\"intro\">Intro</h1>"
+        );
+    }
+}
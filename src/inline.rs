@@ -0,0 +1,1070 @@
+use crate::ast::{plain_text, Inline};
+use crate::entity::decode_entity;
+use crate::sanitize::parse_markup;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Delimiters for highlighted and struck through spans. Emphasis with `*`
+/// and `_` is resolved from delimiter runs instead.
+const STYLE_DELIMITERS: [&str; 2] = ["===", "~~"];
+
+/// The target of a `[label]: url "title"` link reference definition.
+#[derive(Debug, Clone)]
+pub(crate) struct LinkReference {
+    pub url: String,
+    pub title: Option<String>,
+}
+
+/// A run of `*` or `_` that may open or close emphasis.
+struct Delimiter {
+    character: char,
+    /// The number of characters not yet used by emphasis.
+    count: usize,
+    /// The length of the whole run.
+    length: usize,
+    can_open: bool,
+    can_close: bool,
+}
+
+impl Delimiter {
+    /// Returns true if `self` can be the opener of emphasis closed by
+    /// `closer`.
+    fn opens(&self, closer: &Delimiter) -> bool {
+        // A run that can both open and close only matches another one if
+        // their lengths don't add up to a multiple of 3, unless both are.
+        let multiple_of_three = (self.can_close || closer.can_open)
+            && (self.length + closer.length).is_multiple_of(3)
+            && !(self.length.is_multiple_of(3) && closer.length.is_multiple_of(3));
+        self.character == closer.character && self.can_open && !multiple_of_three
+    }
+
+    /// Returns the index of the bound on the opener search for `self` as a
+    /// closer, which depends on its character, can_open and length mod 3.
+    fn bottom_index(&self) -> usize {
+        usize::from(self.character == '_') * 6 + usize::from(self.can_open) * 3 + self.length % 3
+    }
+}
+
+/// An inline node, or a delimiter run waiting to be matched.
+enum Item {
+    Inline(Inline),
+    Delimiter(Delimiter),
+}
+
+/// The closing brackets found so far in a text, keyed by the position
+/// after each opening bracket.
+type Brackets = RefCell<HashMap<usize, Option<usize>>>;
+
+/// How deeply links and images may be nested in the text of others. Deeper
+/// ones are left as text, which keeps the recursion bounded.
+const MAX_LINK_DEPTH: usize = 32;
+
+/// How deeply parentheses may be nested in a link destination, as in cmark.
+const MAX_DESTINATION_DEPTH: usize = 32;
+
+/// Characters after which a bare URL may start.
+const BARE_LINK_BOUNDARIES: [char; 4] = ['*', '_', '~', '('];
+
+/// Parses the inline content of blocks, resolving reference links against
+/// the definitions of the document.
+pub(crate) struct InlineParser<'a> {
+    references: &'a HashMap<String, LinkReference>,
+    /// Turn bare URLs and email addresses into links.
+    autolink: bool,
+    /// The number of links and images around the text being parsed.
+    depth: usize,
+}
+
+impl<'a> InlineParser<'a> {
+    pub fn new(references: &'a HashMap<String, LinkReference>, autolink: bool) -> InlineParser<'a> {
+        InlineParser {
+            references,
+            autolink,
+            depth: 0,
+        }
+    }
+    /// Parses the inline content of a block into a list of [`Inline`] nodes.
+    pub fn parse(&self, text: &str) -> Vec<Inline> {
+        resolve_emphasis(self.parse_items(text, 0, text.len(), &Brackets::default()))
+    }
+    /// Parses `text[start..end]` into inline nodes and delimiter runs. The
+    /// rest of `text` is only looked at to classify delimiter runs.
+    fn parse_items(&self, text: &str, start: usize, end: usize, brackets: &Brackets) -> Vec<Item> {
+        let mut items = Vec::new();
+        let mut buffer = String::new();
+        let mut i = start;
+        while i < end {
+            let rest = &text[i..end];
+            if rest.starts_with("\\\n") {
+                flush_text(&mut buffer, &mut items);
+                items.push(Item::Inline(Inline::HardBreak));
+                i += 2;
+                continue;
+            }
+            if let Some((literal, consumed)) = parse_escape(rest) {
+                buffer.push_str(&literal);
+                i += consumed;
+                continue;
+            }
+            let boundary = text[..i]
+                .chars()
+                .next_back()
+                .is_none_or(|c| c.is_whitespace() || BARE_LINK_BOUNDARIES.contains(&c));
+            let parsed = parse_inline_code(rest)
+                .or_else(|| parse_autolink(rest))
+                .or_else(|| parse_raw_html(rest))
+                .or_else(|| self.parse_image(text, i, end, brackets))
+                .or_else(|| parse_footnote_reference(rest))
+                .or_else(|| self.parse_bare_link(rest, boundary))
+                .or_else(|| self.parse_styles(rest));
+            if let Some((inline, consumed)) = parsed {
+                flush_text(&mut buffer, &mut items);
+                items.push(Item::Inline(inline));
+                i += consumed;
+                continue;
+            }
+            if let Some((link, consumed)) = self.parse_link(text, i, end, brackets) {
+                flush_text(&mut buffer, &mut items);
+                items.extend(link);
+                i += consumed;
+                continue;
+            }
+            let c = rest.chars().next().unwrap();
+            if c == '*' || c == '_' {
+                flush_text(&mut buffer, &mut items);
+                let delimiter = parse_delimiter_run(text, i, c);
+                i += delimiter.length;
+                items.push(Item::Delimiter(delimiter));
+                continue;
+            }
+            if c == '\n' {
+                let line_end = buffer.trim_end_matches(' ').len();
+                let line_break = match buffer.len() - line_end {
+                    0 | 1 => Inline::SoftBreak,
+                    _ => Inline::HardBreak,
+                };
+                buffer.truncate(line_end);
+                flush_text(&mut buffer, &mut items);
+                items.push(Item::Inline(line_break));
+            } else {
+                buffer.push(c);
+            }
+            i += c.len_utf8();
+        }
+        flush_text(&mut buffer, &mut items);
+        items
+    }
+    fn parse_image(
+        &self,
+        text: &str,
+        start: usize,
+        end: usize,
+        brackets: &Brackets,
+    ) -> Option<(Inline, usize)> {
+        if !text[start..end].starts_with('!') {
+            return None;
+        }
+        let (text_end, target, consumed) = self.parse_link_parts(text, start + 1, end, brackets)?;
+        let parser = InlineParser {
+            depth: self.depth + 1,
+            ..*self
+        };
+        let alt = parser.parse_items(text, start + 2, text_end, brackets);
+        let image = Inline::Image {
+            url: target.url,
+            title: target.title,
+            alt: plain_text(&resolve_emphasis(alt)),
+        };
+        Some((image, consumed + 1))
+    }
+    /// Parses the link at `start`, returning its items and length.
+    ///
+    /// Links can't contain other links, so when the link text holds one
+    /// only the `[` is taken, as text, followed by the items of the link
+    /// text. The inner link wins without the text being parsed again.
+    fn parse_link(
+        &self,
+        text: &str,
+        start: usize,
+        end: usize,
+        brackets: &Brackets,
+    ) -> Option<(Vec<Item>, usize)> {
+        let (text_end, target, consumed) = self.parse_link_parts(text, start, end, brackets)?;
+        let parser = InlineParser {
+            references: self.references,
+            autolink: false,
+            depth: self.depth + 1,
+        };
+        let items = parser.parse_items(text, start + 1, text_end, brackets);
+        let nested = items.iter().any(|item| match item {
+            Item::Inline(inline) => contains_link(std::slice::from_ref(inline)),
+            Item::Delimiter(_) => false,
+        });
+        if nested {
+            let mut literal = vec![Item::Inline(Inline::Text(String::from("[")))];
+            literal.extend(items);
+            return Some((literal, text_end - start));
+        }
+        let link = Inline::Link {
+            url: target.url,
+            title: target.title,
+            children: resolve_emphasis(items),
+        };
+        Some((vec![Item::Inline(link)], consumed))
+    }
+    /// Splits the link at `start` in `text` into the end of its text and
+    /// its target, returning them with the length of the link.
+    ///
+    /// The target is either inline, `[text](url)`, or a reference: full
+    /// `[text][label]`, collapsed `[text][]` or shortcut `[text]`.
+    fn parse_link_parts(
+        &self,
+        text: &str,
+        start: usize,
+        end: usize,
+        brackets: &Brackets,
+    ) -> Option<(usize, LinkReference, usize)> {
+        if !text[start..end].starts_with('[') || self.depth == MAX_LINK_DEPTH {
+            return None;
+        }
+        let text_end = find_closing_bracket(text, start + 1, brackets).filter(|&i| i < end)?;
+        let link_text = &text[start + 1..text_end];
+        let after = &text[text_end + 1..end];
+        let consumed = text_end + 1 - start;
+        if let Some((target, length)) = parse_inline_target(after) {
+            return Some((text_end, target, consumed + length));
+        }
+        if let Some(label) = after.strip_prefix('[') {
+            let label_end = label.find(']')?;
+            let label = if label_end == 0 {
+                link_text
+            } else {
+                &label[..label_end]
+            };
+            let target = self.reference(label)?;
+            return Some((text_end, target.clone(), consumed + label_end + 2));
+        }
+        let target = self.reference(link_text)?;
+        Some((text_end, target.clone(), consumed))
+    }
+    /// Returns the definition of the link `label`, which can be at most 999
+    /// characters long.
+    fn reference(&self, label: &str) -> Option<&LinkReference> {
+        if label.chars().nth(999).is_some() {
+            return None;
+        }
+        self.references.get(&normalize_label(label))
+    }
+    /// Parses a bare `www.`, `http://` or `https://` URL or an email address
+    /// when linkification is enabled and `text` starts at a word boundary.
+    fn parse_bare_link(&self, text: &str, boundary: bool) -> Option<(Inline, usize)> {
+        if !self.autolink || !boundary {
+            return None;
+        }
+        let (url, length) = parse_bare_url(text).or_else(|| parse_bare_email(text))?;
+        let link = Inline::Link {
+            url,
+            title: None,
+            children: vec![Inline::Text(text[..length].to_string())],
+        };
+        Some((link, length))
+    }
+    fn parse_styles(&self, text: &str) -> Option<(Inline, usize)> {
+        for delimiter in STYLE_DELIMITERS {
+            let Some(rest) = text.strip_prefix(delimiter) else {
+                continue;
+            };
+            if rest.starts_with(char::is_whitespace) {
+                continue;
+            }
+            let Some(end) = rest.find(delimiter) else {
+                continue;
+            };
+            if end == 0 || rest[..end].ends_with(char::is_whitespace) {
+                continue;
+            }
+            let children = self.parse(&rest[..end]);
+            let inline = match delimiter {
+                "===" => Inline::Mark(children),
+                _ => Inline::Strikethrough(children),
+            };
+            return Some((inline, end + 2 * delimiter.len()));
+        }
+        None
+    }
+}
+
+fn flush_text(buffer: &mut String, items: &mut Vec<Item>) {
+    if !buffer.is_empty() {
+        items.push(Item::Inline(Inline::Text(std::mem::take(buffer))));
+    }
+}
+
+/// Parses the run of `character` starting at `start` in `text`, deciding
+/// from its neighbours whether it can open or close emphasis.
+fn parse_delimiter_run(text: &str, start: usize, character: char) -> Delimiter {
+    let length = text[start..]
+        .find(|c| c != character)
+        .unwrap_or(text.len() - start);
+    let before = text[..start].chars().next_back();
+    let after = text[start + length..].chars().next();
+    let is_space = |c: Option<char>| c.is_none_or(char::is_whitespace);
+    let is_punctuation = |c: Option<char>| c.is_some_and(is_punctuation);
+    let left_flanking =
+        !is_space(after) && (!is_punctuation(after) || is_space(before) || is_punctuation(before));
+    let right_flanking =
+        !is_space(before) && (!is_punctuation(before) || is_space(after) || is_punctuation(after));
+    let (can_open, can_close) = match character {
+        '*' => (left_flanking, right_flanking),
+        // `_` doesn't emphasize parts of words, as in `snake_case_name`.
+        _ => (
+            left_flanking && (!right_flanking || is_punctuation(before)),
+            right_flanking && (!left_flanking || is_punctuation(after)),
+        ),
+    };
+    Delimiter {
+        character,
+        count: length,
+        length,
+        can_open,
+        can_close,
+    }
+}
+
+/// Returns true for ASCII punctuation and for other symbols and
+/// punctuation marks.
+fn is_punctuation(c: char) -> bool {
+    c.is_ascii_punctuation() || !(c.is_ascii() || c.is_alphanumeric() || c.is_whitespace())
+}
+
+/// Matches the delimiter runs of `items` into emphasis, working from each
+/// closer back to the nearest opener, and turns the rest into text.
+///
+/// The items are kept in a linked list so wrapping emphasis doesn't move
+/// the others, and the search for an opener stops where an earlier search
+/// for the same kind of closer failed, as CommonMark describes.
+fn resolve_emphasis(items: Vec<Item>) -> Vec<Inline> {
+    // Slot 0 is the head of the list, new emphasis goes at the end.
+    let mut slots: Vec<Option<Item>> = Vec::with_capacity(items.len() + 1);
+    slots.push(None);
+    slots.extend(items.into_iter().map(Some));
+    let length = slots.len();
+    let mut next: Vec<Option<usize>> = (1..length).map(Some).chain([None]).collect();
+    let mut prev: Vec<usize> = (0..length).map(|i| i.saturating_sub(1)).collect();
+    // The delimiter before each one that may still open emphasis, or 0.
+    let mut previous = vec![0; length];
+    // The lowest opener to look at, by character, can_open and length mod 3.
+    let mut bottoms = [0; 12];
+    let mut last = 0;
+    for closer in 1..length {
+        let Some(Item::Delimiter(delimiter)) = &slots[closer] else {
+            continue;
+        };
+        previous[closer] = last;
+        let can_open = delimiter.can_open;
+        if delimiter.can_close {
+            let bottom = &mut bottoms[delimiter.bottom_index()];
+            while delimiter_count(&slots[closer]) > 0 {
+                let mut opener = previous[closer];
+                while opener != 0 && opener >= *bottom && !opens(&slots[opener], &slots[closer]) {
+                    opener = previous[opener];
+                }
+                if opener == 0 || opener < *bottom {
+                    *bottom = closer;
+                    break;
+                }
+                let count =
+                    match delimiter_count(&slots[opener]).min(delimiter_count(&slots[closer])) {
+                        1 => 1,
+                        _ => 2,
+                    };
+                let mut children = Vec::new();
+                let mut child = next[opener];
+                while let Some(i) = child.filter(|&i| i != closer) {
+                    children.extend(slots[i].take());
+                    child = next[i];
+                }
+                let children = into_inlines(children);
+                let emphasis = match count {
+                    2 => Inline::Strong(children),
+                    _ => Inline::Emphasis(children),
+                };
+                let node = slots.len();
+                slots.push(Some(Item::Inline(emphasis)));
+                next.push(Some(closer));
+                prev.push(opener);
+                next[opener] = Some(node);
+                prev[closer] = node;
+                // The delimiters between them are now text.
+                previous[closer] = opener;
+                if use_delimiter(&mut slots[opener], count) {
+                    next[prev[opener]] = Some(node);
+                    prev[node] = prev[opener];
+                    previous[closer] = previous[opener];
+                }
+                if use_delimiter(&mut slots[closer], count) {
+                    next[node] = next[closer];
+                    if let Some(after) = next[closer] {
+                        prev[after] = node;
+                    }
+                }
+            }
+        }
+        last = if can_open && delimiter_count(&slots[closer]) > 0 {
+            closer
+        } else {
+            previous[closer]
+        };
+    }
+    let mut items = Vec::new();
+    let mut item = next[0];
+    while let Some(i) = item {
+        items.extend(slots[i].take());
+        item = next[i];
+    }
+    into_inlines(items)
+}
+
+/// Returns true if the delimiter run in `opener` can open emphasis closed by
+/// the one in `closer`.
+fn opens(opener: &Option<Item>, closer: &Option<Item>) -> bool {
+    match (opener, closer) {
+        (Some(Item::Delimiter(opener)), Some(Item::Delimiter(closer))) => opener.opens(closer),
+        _ => false,
+    }
+}
+
+/// Returns the number of characters left in the delimiter run of `item`.
+fn delimiter_count(item: &Option<Item>) -> usize {
+    match item {
+        Some(Item::Delimiter(delimiter)) => delimiter.count,
+        _ => 0,
+    }
+}
+
+/// Takes `count` characters from the delimiter run of `item`, returning true
+/// if none are left.
+fn use_delimiter(item: &mut Option<Item>, count: usize) -> bool {
+    match item {
+        Some(Item::Delimiter(delimiter)) => {
+            delimiter.count -= count;
+            delimiter.count == 0
+        }
+        _ => false,
+    }
+}
+
+/// Turns the unmatched delimiter runs of `items` into text.
+fn into_inlines(items: Vec<Item>) -> Vec<Inline> {
+    let mut inlines = Vec::new();
+    for item in items {
+        let inline = match item {
+            Item::Inline(inline) => inline,
+            Item::Delimiter(delimiter) => {
+                Inline::Text(delimiter.character.to_string().repeat(delimiter.count))
+            }
+        };
+        match (inlines.last_mut(), inline) {
+            (Some(Inline::Text(last)), Inline::Text(text)) => last.push_str(&text),
+            (_, inline) => inlines.push(inline),
+        }
+    }
+    inlines
+}
+
+/// Parses a backslash escape of an ASCII punctuation character or an
+/// entity, returning the literal text.
+fn parse_escape(text: &str) -> Option<(String, usize)> {
+    if let Some(rest) = text.strip_prefix('\\') {
+        let c = rest.chars().next().filter(char::is_ascii_punctuation)?;
+        return Some((c.to_string(), 2));
+    }
+    decode_entity(text)
+}
+
+/// Replaces the backslash escapes and entities of `text` with the
+/// characters they stand for.
+pub(crate) fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        if let Some((literal, consumed)) = parse_escape(&text[i..]) {
+            unescaped.push_str(&literal);
+            i += consumed;
+        } else {
+            let c = text[i..].chars().next().unwrap();
+            unescaped.push(c);
+            i += c.len_utf8();
+        }
+    }
+    unescaped
+}
+
+/// Parses a code span opened by a run of backticks and closed by a run of
+/// the same length. Without a closing run, the opening one is plain text.
+fn parse_inline_code(text: &str) -> Option<(Inline, usize)> {
+    let fence = text.len() - text.trim_start_matches('`').len();
+    if fence == 0 {
+        return None;
+    }
+    let Some(length) = code_span_length(text) else {
+        return Some((Inline::Text(text[..fence].to_string()), fence));
+    };
+    let code = text[fence..length - fence].replace('\n', " ");
+    // One space is stripped from each side, so that spans can start or
+    // end with a backtick.
+    let code = match code
+        .strip_prefix(' ')
+        .and_then(|code| code.strip_suffix(' '))
+    {
+        Some(stripped) if !code.trim_start_matches(' ').is_empty() => stripped.to_string(),
+        _ => code,
+    };
+    Some((Inline::Code(code), length))
+}
+
+/// Returns the length of the code span at the start of `text`, including
+/// its backticks.
+fn code_span_length(text: &str) -> Option<usize> {
+    let fence = text.len() - text.trim_start_matches('`').len();
+    let mut i = fence;
+    while let Some(start) = text[i..].find('`') {
+        let start = i + start;
+        let run = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if run == fence {
+            return Some(start + run);
+        }
+        i = start + run;
+    }
+    None
+}
+
+fn parse_footnote_reference(text: &str) -> Option<(Inline, usize)> {
+    let rest = text.strip_prefix("[^")?;
+    let end = rest.find(|c: char| c == ']' || c == '[' || c.is_whitespace())?;
+    let label = &rest[..end];
+    if label.is_empty() || !rest[end..].starts_with(']') {
+        return None;
+    }
+    Some((Inline::FootnoteReference(label.to_string()), end + 3))
+}
+
+/// Parses a `<scheme:…>` or `<user@host>` autolink.
+fn parse_autolink(text: &str) -> Option<(Inline, usize)> {
+    let rest = text.strip_prefix('<')?;
+    let end = rest.find(|c: char| c == '>' || c == '<' || c.is_whitespace())?;
+    if !rest[end..].starts_with('>') {
+        return None;
+    }
+    let target = &rest[..end];
+    let url = if is_absolute_uri(target) {
+        target.to_string()
+    } else if is_email_address(target) {
+        format!("mailto:{}", target)
+    } else {
+        return None;
+    };
+    let link = Inline::Link {
+        url,
+        title: None,
+        children: vec![Inline::Text(target.to_string())],
+    };
+    Some((link, end + 2))
+}
+
+/// Parses an inline HTML tag, comment or declaration.
+fn parse_raw_html(text: &str) -> Option<(Inline, usize)> {
+    let (_, length) = parse_markup(text)?;
+    Some((Inline::Html(text[..length].to_string()), length))
+}
+
+/// Returns true if `text` starts with a scheme of 2 to 32 characters
+/// followed by `:`.
+fn is_absolute_uri(text: &str) -> bool {
+    let Some((scheme, _)) = text.split_once(':') else {
+        return false;
+    };
+    (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c))
+}
+
+fn is_email_address(text: &str) -> bool {
+    let Some((local, domain)) = text.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c))
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// Parses a bare URL, returning its target and length without the trailing
+/// punctuation.
+fn parse_bare_url(text: &str) -> Option<(String, usize)> {
+    let prefix = ["https://", "http://", "www."].into_iter().find(|prefix| {
+        text.as_bytes()
+            .get(..prefix.len())
+            .is_some_and(|start| start.eq_ignore_ascii_case(prefix.as_bytes()))
+    })?;
+    let end = text
+        .find(|c: char| c.is_whitespace() || c == '<')
+        .unwrap_or(text.len());
+    let url = trim_bare_url(&text[..end]);
+    let domain = url
+        .get(prefix.len()..)?
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap();
+    if domain.is_empty() || (prefix == "www." && !domain.contains('.')) {
+        return None;
+    }
+    let target = match prefix {
+        "www." => format!("http://{}", url),
+        _ => url.to_string(),
+    };
+    Some((target, url.len()))
+}
+
+/// Removes the punctuation and unbalanced closing parentheses that end a
+/// sentence rather than a bare URL.
+fn trim_bare_url(mut url: &str) -> &str {
+    loop {
+        if let Some(trimmed) = url.strip_suffix(['?', '!', '.', ',', ':', '*', '_', '~', '\'', '"'])
+        {
+            url = trimmed;
+        } else if url.ends_with(')') && url.matches(')').count() > url.matches('(').count() {
+            url = &url[..url.len() - 1];
+        } else {
+            return url;
+        }
+    }
+}
+
+/// Parses a bare email address, returning its `mailto:` target and length.
+fn parse_bare_email(text: &str) -> Option<(String, usize)> {
+    let at = text.find(|c: char| !(c.is_ascii_alphanumeric() || ".+-_".contains(c)))?;
+    if at == 0 || !text[at..].starts_with('@') {
+        return None;
+    }
+    let domain_length = text[at + 1..]
+        .find(|c: char| !(c.is_ascii_alphanumeric() || ".-_".contains(c)))
+        .unwrap_or(text.len() - at - 1);
+    let domain = text[at + 1..at + 1 + domain_length].trim_end_matches('.');
+    if !domain.contains('.') || domain.ends_with(['-', '_']) {
+        return None;
+    }
+    let address = &text[..at + 1 + domain.len()];
+    Some((format!("mailto:{}", address), address.len()))
+}
+
+/// Returns true if `inlines` contain a link at any depth.
+fn contains_link(inlines: &[Inline]) -> bool {
+    inlines.iter().any(|inline| match inline {
+        Inline::Link { .. } => true,
+        Inline::Emphasis(children)
+        | Inline::Strong(children)
+        | Inline::Mark(children)
+        | Inline::Strikethrough(children) => contains_link(children),
+        _ => false,
+    })
+}
+
+/// Returns the position of the `]` matching the opening bracket just
+/// before `start` in `text`, skipping nested pairs, escaped brackets and
+/// code spans.
+///
+/// The matches of the nested brackets are remembered in `brackets`, so
+/// that each part of the text is only searched once.
+fn find_closing_bracket(text: &str, start: usize, brackets: &Brackets) -> Option<usize> {
+    let mut brackets = brackets.borrow_mut();
+    if let Some(&end) = brackets.get(&start) {
+        return end;
+    }
+    // The starts of the brackets not closed yet.
+    let mut open = vec![start];
+    let mut i = start;
+    while i < text.len() {
+        let rest = &text[i..];
+        let c = rest.chars().next().unwrap();
+        match c {
+            '\\' => i += rest[1..].chars().next().map_or(0, char::len_utf8),
+            '`' => {
+                let fence = rest.len() - rest.trim_start_matches('`').len();
+                i += code_span_length(rest).unwrap_or(fence) - 1;
+            }
+            '[' => open.push(i + 1),
+            ']' => {
+                brackets.insert(open.pop().unwrap(), Some(i));
+                if open.is_empty() {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    for start in open {
+        brackets.insert(start, None);
+    }
+    None
+}
+
+/// Parses the `(url "title")` following the text of an inline link,
+/// returning the target and the length of the parenthesized part.
+fn parse_inline_target(text: &str) -> Option<(LinkReference, usize)> {
+    let rest = skip_spaces_and_newline(text.strip_prefix('(')?);
+    let (url, rest) = match rest.strip_prefix(')') {
+        Some(_) => (String::new(), rest),
+        None => parse_link_destination(rest)?,
+    };
+    // The title has to be separated from the url by whitespace.
+    let title_start = skip_spaces_and_newline(rest);
+    let (title, rest) = match parse_link_title(title_start) {
+        Some((title, after_title)) if title_start.len() < rest.len() => (Some(title), after_title),
+        _ => (None, title_start),
+    };
+    let rest = skip_spaces_and_newline(rest).strip_prefix(')')?;
+    Some((LinkReference { url, title }, text.len() - rest.len()))
+}
+
+/// Normalizes a link label for matching: case-folded, with runs of
+/// whitespace collapsed.
+pub(crate) fn normalize_label(label: &str) -> String {
+    fold_case(&label.split_whitespace().collect::<Vec<&str>>().join(" "))
+}
+
+/// Applies Unicode full case folding, under which `ß`, `ẞ` and `SS` all
+/// become `ss`.
+///
+/// Lowercasing the uppercase of the lowercase gives the full folding of
+/// every character but the dotless `ı`, which folds to itself.
+fn fold_case(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == 'ı' {
+            folded.push(c);
+            continue;
+        }
+        for lower in c.to_lowercase() {
+            for upper in lower.to_uppercase() {
+                folded.extend(upper.to_lowercase());
+            }
+        }
+    }
+    folded
+}
+
+/// Parses a link reference definition, `[label]: url "title"`, at the start
+/// of the text of a paragraph. The url and the title may each be on the
+/// next line.
+///
+/// Returns the normalized label, the target and the length of the
+/// definition including its final newline.
+pub(crate) fn parse_link_definition(text: &str) -> Option<(String, LinkReference, usize)> {
+    let rest = text.strip_prefix('[')?;
+    let label_end = rest.find(']')?;
+    let label = &rest[..label_end];
+    if label.trim().is_empty() || label.contains('[') || label.starts_with('^') {
+        return None;
+    }
+    let rest = rest[label_end + 1..].strip_prefix(':')?;
+    let rest = skip_spaces_and_newline(rest);
+    let (url, rest) = parse_link_destination(rest)?;
+    let definition_end = |rest: &str| {
+        let line = rest.split('\n').next().unwrap();
+        if !line.trim().is_empty() {
+            return None;
+        }
+        Some(text.len() - rest.len() + (line.len() + 1).min(rest.len()))
+    };
+    let title_start = skip_spaces_and_newline(rest);
+    if title_start.len() < rest.len() {
+        if let Some((title, after_title)) = parse_link_title(title_start) {
+            if let Some(consumed) = definition_end(after_title) {
+                let target = LinkReference {
+                    url,
+                    title: Some(title),
+                };
+                return Some((normalize_label(label), target, consumed));
+            }
+        }
+    }
+    let consumed = definition_end(rest)?;
+    let target = LinkReference { url, title: None };
+    Some((normalize_label(label), target, consumed))
+}
+
+/// Skips spaces and tabs, and at most one newline.
+fn skip_spaces_and_newline(text: &str) -> &str {
+    let text = text.trim_start_matches([' ', '\t']);
+    let text = text.strip_prefix('\n').unwrap_or(text);
+    text.trim_start_matches([' ', '\t'])
+}
+
+/// Parses a link destination, either `<url>` or a url without spaces and
+/// with balanced parentheses.
+///
+/// Both stop early on text that can't be a destination, an unescaped `<`
+/// or parentheses nested too deeply, so unclosed ones aren't searched to
+/// the end of the text again and again.
+fn parse_link_destination(text: &str) -> Option<(String, &str)> {
+    if let Some(rest) = text.strip_prefix('<') {
+        let mut escaped = false;
+        let end = rest.find(|c| {
+            let stop = c == '\n' || (matches!(c, '<' | '>') && !escaped);
+            escaped = c == '\\' && !escaped;
+            stop
+        })?;
+        if !rest[end..].starts_with('>') {
+            return None;
+        }
+        return Some((unescape(&rest[..end]), &rest[end + 1..]));
+    }
+    let mut depth = 0;
+    let mut end = text.len();
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '(' if depth == MAX_DESTINATION_DEPTH => return None,
+            '(' => depth += 1,
+            ')' if depth == 0 => {
+                end = i;
+                break;
+            }
+            ')' => depth -= 1,
+            _ if c.is_whitespace() => {
+                end = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    if end == 0 || depth > 0 {
+        return None;
+    }
+    Some((unescape(&text[..end]), &text[end..]))
+}
+
+/// Parses a link title in `"…"`, `'…'` or `(…)`. A title in parentheses
+/// can't hold an unescaped `(`.
+fn parse_link_title(text: &str) -> Option<(String, &str)> {
+    let open = text.chars().next()?;
+    let close = match open {
+        '"' => '"',
+        '\'' => '\'',
+        '(' => ')',
+        _ => return None,
+    };
+    let rest = &text[1..];
+    let mut escaped = false;
+    let end = rest.find(|c| {
+        let stop = (c == close || c == open) && !escaped;
+        escaped = c == '\\' && !escaped;
+        stop
+    })?;
+    if !rest[end..].starts_with(close) {
+        return None;
+    }
+    Some((unescape(&rest[..end]), &rest[end + 1..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<Inline> {
+        InlineParser::new(&HashMap::new(), false).parse(text)
+    }
+
+    fn text(text: &str) -> Inline {
+        Inline::Text(text.to_string())
+    }
+
+    #[test]
+    fn matches_nested_emphasis() {
+        assert_eq!(
+            parse("*a **b** c*"),
+            vec![Inline::Emphasis(vec![
+                text("a "),
+                Inline::Strong(vec![text("b")]),
+                text(" c"),
+            ])]
+        );
+        assert_eq!(
+            parse("***a** b*"),
+            vec![Inline::Emphasis(vec![
+                Inline::Strong(vec![text("a")]),
+                text(" b")
+            ])]
+        );
+    }
+
+    #[test]
+    fn follows_the_rule_of_three() {
+        assert_eq!(
+            parse("*foo**bar**baz*"),
+            vec![Inline::Emphasis(vec![
+                text("foo"),
+                Inline::Strong(vec![text("bar")]),
+                text("baz"),
+            ])]
+        );
+    }
+
+    #[test]
+    fn rejects_links_inside_links() {
+        let inner = Inline::Link {
+            url: String::from("b"),
+            title: None,
+            children: vec![text("a")],
+        };
+        assert_eq!(
+            parse("[[a](b)](c)"),
+            vec![text("["), inner.clone(), text("](c)")]
+        );
+        assert_eq!(
+            parse("[*[a](b)*](c)"),
+            vec![text("["), Inline::Emphasis(vec![inner]), text("](c)")]
+        );
+    }
+
+    #[test]
+    fn parses_deeply_nested_links_once() {
+        let depth = 1000;
+        let text = format!("{}a{}", "[".repeat(depth), "](b)".repeat(depth));
+        let inlines = parse(&text);
+        let links = inlines
+            .iter()
+            .filter(|inline| matches!(inline, Inline::Link { .. }))
+            .count();
+        assert_eq!(links, 1);
+    }
+
+    #[test]
+    fn parses_link_destinations_and_titles() {
+        let target =
+            |text: &str| parse_inline_target(text).map(|(target, _)| (target.url, target.title));
+        assert_eq!(target("(<b\\>c>)"), Some((String::from("b>c"), None)));
+        assert_eq!(target("(<b<c>)"), None);
+        assert_eq!(target("(((x)))"), Some((String::from("((x))"), None)));
+        assert_eq!(
+            target(&format!("({}x{})", "(".repeat(40), ")".repeat(40))),
+            None
+        );
+        assert_eq!(
+            target("(q (t\\(u))"),
+            Some((String::from("q"), Some(String::from("t(u"))))
+        );
+        assert_eq!(target("(q (t(u)))"), None);
+    }
+
+    #[test]
+    fn matches_brackets_in_link_text() {
+        let link = |children| Inline::Link {
+            url: String::from("u"),
+            title: None,
+            children,
+        };
+        assert_eq!(parse("[a [b] c](u)"), vec![link(vec![text("a [b] c")])]);
+        assert_eq!(
+            parse("[[a [b](u)"),
+            vec![text("[[a "), link(vec![text("b")])]
+        );
+        assert_eq!(
+            parse("[a`]`](u)"),
+            vec![link(vec![text("a"), Inline::Code(String::from("]"))])]
+        );
+    }
+
+    #[test]
+    fn leaves_unmatched_delimiters_as_text() {
+        assert_eq!(
+            parse("*a _b* c_"),
+            vec![Inline::Emphasis(vec![text("a _b")]), text(" c_")]
+        );
+        assert_eq!(parse("a* b* c*").len(), 1);
+    }
+
+    #[test]
+    fn parses_code_spans() {
+        assert_eq!(
+            parse("a ``b ` c`` d"),
+            vec![text("a "), Inline::Code(String::from("b ` c")), text(" d")]
+        );
+        assert_eq!(
+            parse("`a *b*"),
+            vec![text("`a "), Inline::Emphasis(vec![text("b")])]
+        );
+    }
+
+    #[test]
+    fn parses_line_breaks() {
+        assert_eq!(
+            parse("a\nb  \nc\\\nd"),
+            vec![
+                text("a"),
+                Inline::SoftBreak,
+                text("b"),
+                Inline::HardBreak,
+                text("c"),
+                Inline::HardBreak,
+                text("d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_escapes_and_entities() {
+        assert_eq!(
+            parse(r"\*a\* &copy; &#65; &bogus;"),
+            vec![text("*a* © A &bogus;")]
+        );
+    }
+
+    #[test]
+    fn parses_autolinks_and_images() {
+        assert_eq!(
+            parse("<https://a.b> ![*x*](i.png \"t\")"),
+            vec![
+                Inline::Link {
+                    url: String::from("https://a.b"),
+                    title: None,
+                    children: vec![text("https://a.b")],
+                },
+                text(" "),
+                Inline::Image {
+                    url: String::from("i.png"),
+                    title: Some(String::from("t")),
+                    alt: String::from("x"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn links_bare_urls_only_when_enabled() {
+        let references = HashMap::new();
+        let linked = InlineParser::new(&references, true).parse("see www.a.b.");
+        assert!(matches!(&linked[1], Inline::Link { url, .. } if url == "http://www.a.b"));
+        assert_eq!(parse("see www.a.b."), vec![text("see www.a.b.")]);
+    }
+
+    #[test]
+    fn folds_the_case_of_labels() {
+        assert_eq!(normalize_label("Straẞe"), "strasse");
+        assert_eq!(normalize_label("STRASSE  \n ﬀ"), "strasse ff");
+        assert_eq!(normalize_label("ΣΑΣ"), normalize_label("σας"));
+        assert_ne!(normalize_label("ı"), normalize_label("i"));
+    }
+}
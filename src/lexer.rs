@@ -0,0 +1,1065 @@
+use crate::ast::Inline;
+use crate::ast::{Alignment, Block, CodeBlock, Document, List, ListItem, Table};
+use crate::inline::{parse_link_definition, unescape, InlineParser, LinkReference};
+use crate::sanitize::{parse_markup, Markup};
+use regex::Regex;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+// The block patterns are compiled once, on first use.
+static HEADING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ {0,3}(#{1,6})(?:[ \t]+(.*))?$").unwrap());
+static SETEXT_UNDERLINE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ {0,3}(=+|-+)[ \t]*$").unwrap());
+static BLOCKQUOTE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^ {0,3}> ?").unwrap());
+static THEMATIC_BREAK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ {0,3}((\*[ \t]*){3,}|(-[ \t]*){3,}|(_[ \t]*){3,})$").unwrap());
+static FENCE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^( {0,3})(`{3,}|~{3,})[ \t]*(.*)$").unwrap());
+static LIST_ITEM: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^( {0,3})([-*+]|([0-9]{1,9})[.)])( |$)").unwrap());
+static TASK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\[([ xX])\] +").unwrap());
+static FOOTNOTE_DEFINITION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^ {0,3}\[\^([^\]\s]+)\]: ?").unwrap());
+
+/// Parses Markdown into a [`Document`] tree.
+pub struct Lexer {
+    /// The link reference definitions of the document being parsed.
+    references: RefCell<HashMap<String, LinkReference>>,
+    /// Set during the first pass, which only collects the definitions.
+    collecting: Cell<bool>,
+    /// Turn bare URLs and email addresses into links.
+    autolink: bool,
+    /// The number of containers around the lines being parsed.
+    depth: Cell<usize>,
+}
+
+/// How deeply container blocks may be nested. Markers beyond it are left as
+/// text, which keeps the recursion bounded.
+const MAX_NESTING: usize = 32;
+
+/// Tags that start an HTML block which may interrupt a paragraph.
+const HTML_BLOCK_TAGS: [&str; 62] = [
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+/// Tags whose HTML block runs until their closing tag, blank lines included.
+const HTML_RAW_TAGS: [&str; 4] = ["script", "pre", "style", "textarea"];
+
+/// How an HTML block ends.
+enum HtmlBlockEnd {
+    /// On the first line containing any of these.
+    Text(&'static [&'static str]),
+    BlankLine,
+}
+
+/// The marker that opens a list item.
+struct ListMarker {
+    /// The bullet character, or the `.` or `)` following the number.
+    delimiter: char,
+    ordered: bool,
+    start: u64,
+    /// The item has no content on its first line.
+    empty: bool,
+    /// Column at which the content of the item starts.
+    content_indent: usize,
+}
+
+/// What is known about whether the lines collected for a container end in
+/// a paragraph, which a lazy continuation line may continue.
+#[derive(Default)]
+struct LazyContinuation {
+    /// The first line of the last block, where parsing resumes.
+    start: usize,
+    /// Known while every line since the last parse continued a paragraph.
+    paragraph: Option<bool>,
+}
+
+impl Default for Lexer {
+    fn default() -> Lexer {
+        Lexer::new()
+    }
+}
+
+impl Lexer {
+    pub fn new() -> Lexer {
+        Lexer {
+            references: RefCell::new(HashMap::new()),
+            collecting: Cell::new(false),
+            autolink: false,
+            depth: Cell::new(0),
+        }
+    }
+    /// Turns bare `www.` and `http(s)://` URLs and email addresses into
+    /// links, as GitHub does.
+    pub fn autolink(mut self, enabled: bool) -> Lexer {
+        self.autolink = enabled;
+        self
+    }
+    pub fn parse(&self, markdown: &str) -> Document {
+        let lines: Vec<String> = markdown.lines().map(String::from).collect();
+        // Link reference definitions may follow the links using them, so a
+        // first pass collects them before the inlines are parsed.
+        self.references.borrow_mut().clear();
+        self.collecting.set(true);
+        self.parse_blocks(&lines);
+        self.collecting.set(false);
+        Document {
+            blocks: self.parse_blocks(&lines),
+        }
+    }
+    fn parse_inlines(&self, text: &str) -> Vec<Inline> {
+        if self.collecting.get() {
+            return Vec::new();
+        }
+        InlineParser::new(&self.references.borrow(), self.autolink).parse(text)
+    }
+    fn parse_blocks(&self, lines: &[String]) -> Vec<Block> {
+        self.parse_block_sequence(lines).0
+    }
+    /// Parses `lines` as blocks, also returning whether any two of them are
+    /// separated by a blank line.
+    fn parse_block_sequence(&self, lines: &[String]) -> (Vec<Block>, bool) {
+        let mut blocks = Vec::new();
+        let mut spaced = false;
+        let mut i = 0;
+        while i < lines.len() {
+            if lines[i].trim().is_empty() {
+                spaced |= !blocks.is_empty() && i + 1 < lines.len();
+                i += 1;
+                continue;
+            }
+            let (block, consumed) = self.parse_block(&lines[i..]);
+            blocks.extend(block);
+            i += consumed;
+        }
+        (blocks, spaced)
+    }
+    /// Parses the block starting at the first line of `lines`, which is not
+    /// blank. No block is returned for link reference definitions.
+    fn parse_block(&self, rest: &[String]) -> (Option<Block>, usize) {
+        match self
+            .parse_code(rest)
+            .or_else(|| self.parse_indented_code(rest))
+            .or_else(|| self.parse_line(rest))
+            .or_else(|| parse_html_block(rest))
+            .or_else(|| self.parse_blockquote(rest))
+            .or_else(|| self.parse_footnote_definition(rest))
+            .or_else(|| self.parse_list(rest))
+            .or_else(|| self.parse_table(rest))
+        {
+            Some((block, consumed)) => (Some(block), consumed),
+            None => self.parse_paragraph(rest),
+        }
+    }
+    /// Returns true if `lines`, the content of a container, end with a
+    /// paragraph, possibly nested in another container, that a lazy
+    /// continuation line can continue.
+    ///
+    /// The lines are only parsed again, from the start of their last block,
+    /// when a line since the last call may have ended the paragraph.
+    fn ends_in_paragraph(&self, lines: &[String], lazy: &mut LazyContinuation) -> bool {
+        if lines.last().is_none_or(|line| line.trim().is_empty()) {
+            return false;
+        }
+        if let Some(paragraph) = lazy.paragraph {
+            return paragraph;
+        }
+        let mut last = None;
+        let mut i = lazy.start;
+        self.nested(|| {
+            while i < lines.len() {
+                if lines[i].trim().is_empty() {
+                    i += 1;
+                    continue;
+                }
+                let (block, consumed) = self.parse_block(&lines[i..]);
+                if block.is_some() {
+                    lazy.start = i;
+                    last = block;
+                }
+                i += consumed;
+            }
+        });
+        let paragraph = last.is_some_and(|block| is_open_paragraph(&block));
+        lazy.paragraph = Some(paragraph);
+        paragraph
+    }
+    /// Records that `line` was added to the content of a container.
+    fn track_line(&self, line: &str, lazy: &mut LazyContinuation) {
+        if lazy.paragraph != Some(true) || !self.keeps_paragraph(line) {
+            lazy.paragraph = None;
+        }
+    }
+    /// Returns true if `line`, following a paragraph, surely leaves the
+    /// content ending in one: it continues the paragraph, or opens
+    /// blockquotes or list items starting a new one.
+    fn keeps_paragraph(&self, line: &str) -> bool {
+        let mut text = line;
+        loop {
+            if let Some(marker) = BLOCKQUOTE.find(text) {
+                text = &text[marker.end()..];
+            } else if let Some(marker) = self.list_marker(text) {
+                if marker.empty {
+                    return false;
+                }
+                text = &text[marker.content_indent..];
+            } else {
+                break;
+            }
+        }
+        !text.trim().is_empty()
+            && strip_code_indent(text).is_none()
+            && !self.starts_block(text)
+            && !SETEXT_UNDERLINE.is_match(text)
+            && table_alignments(text).is_none()
+    }
+    /// Runs `parse` on the content of a container, one level deeper.
+    fn nested<T>(&self, parse: impl FnOnce() -> T) -> T {
+        self.depth.set(self.depth.get() + 1);
+        let parsed = parse();
+        self.depth.set(self.depth.get() - 1);
+        parsed
+    }
+    /// Parses single-line blocks: headings, thematic breaks and `[[TOC]]`.
+    fn parse_line(&self, lines: &[String]) -> Option<(Block, usize)> {
+        let line = &lines[0];
+        if let Some(captures) = HEADING.captures(line) {
+            let text = captures.get(2).map_or("", |text| text.as_str());
+            let heading = Block::Heading {
+                level: captures[1].len() as u8,
+                content: self.parse_inlines(strip_closing_sequence(text)),
+                id: None,
+            };
+            return Some((heading, 1));
+        }
+        if THEMATIC_BREAK.is_match(line) {
+            return Some((Block::ThematicBreak, 1));
+        }
+        if line.trim() == "[[TOC]]" {
+            return Some((Block::TableOfContents(Vec::new()), 1));
+        }
+        None
+    }
+    /// Parses a blockquote, recursing into the lines without their `>`.
+    ///
+    /// Lines without a `>` continue the quote if they continue a paragraph.
+    fn parse_blockquote(&self, lines: &[String]) -> Option<(Block, usize)> {
+        if !BLOCKQUOTE.is_match(&lines[0]) || self.depth.get() == MAX_NESTING {
+            return None;
+        }
+        let mut quote_lines: Vec<String> = Vec::new();
+        let mut lazy = LazyContinuation::default();
+        for line in lines {
+            if let Some(marker) = BLOCKQUOTE.find(line) {
+                let content = &line[marker.end()..];
+                self.track_line(content, &mut lazy);
+                quote_lines.push(content.to_string());
+                continue;
+            }
+            let continues = !line.trim().is_empty()
+                && !self.starts_block(line)
+                && self.ends_in_paragraph(&quote_lines, &mut lazy);
+            if !continues {
+                break;
+            }
+            self.track_line(line, &mut lazy);
+            quote_lines.push(line.clone());
+        }
+        let consumed = quote_lines.len();
+        let blocks = self.nested(|| self.parse_blocks(&quote_lines));
+        Some((Block::BlockQuote(blocks), consumed))
+    }
+    /// Parses a fenced code block, keeping its content line for line.
+    ///
+    /// Indentation of the opening fence is removed from each content line,
+    /// everything else is kept as written. A block that is not closed runs
+    /// to the end of the document.
+    fn parse_code(&self, lines: &[String]) -> Option<(Block, usize)> {
+        let (indent, fence, info) = self.opening_fence(&lines[0])?;
+        let info = info.trim().to_string();
+        let mut literal = String::new();
+        let mut consumed = 1;
+        for line in &lines[1..] {
+            consumed += 1;
+            if is_closing_fence(line, fence) {
+                break;
+            }
+            literal.push_str(strip_indent(line, indent));
+            literal.push('\n');
+        }
+        let (language, attributes) = parse_info_string(&info);
+        let code = CodeBlock {
+            info,
+            language,
+            attributes,
+            literal,
+        };
+        Some((Block::CodeBlock(code), consumed))
+    }
+    /// Returns the indentation, the fence and the info string of a line
+    /// opening a fenced code block.
+    fn opening_fence<'l>(&self, line: &'l str) -> Option<(usize, &'l str, &'l str)> {
+        let captures = FENCE.captures(line)?;
+        let (fence, info) = (captures.get(2)?.as_str(), captures.get(3)?.as_str());
+        // The info string of a backtick fence can't contain backticks.
+        if fence.starts_with('`') && info.contains('`') {
+            return None;
+        }
+        Some((captures[1].len(), fence, info))
+    }
+    /// Parses a code block made of lines indented by four spaces or a tab,
+    /// which can't interrupt a paragraph.
+    fn parse_indented_code(&self, lines: &[String]) -> Option<(Block, usize)> {
+        strip_code_indent(&lines[0])?;
+        let mut code_lines = Vec::new();
+        for line in lines {
+            match strip_code_indent(line) {
+                Some(code) => code_lines.push(code),
+                None if line.trim().is_empty() => code_lines.push(""),
+                None => break,
+            }
+        }
+        // Blank lines after the code belong to the next block.
+        while code_lines.last() == Some(&"") {
+            code_lines.pop();
+        }
+        let consumed = code_lines.len();
+        let literal = code_lines
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let code = CodeBlock {
+            info: String::new(),
+            language: None,
+            attributes: Vec::new(),
+            literal,
+        };
+        Some((Block::CodeBlock(code), consumed))
+    }
+    /// Parses a list, recursing into the blocks of each item.
+    ///
+    /// The list ends at the first line that is not an item with the same
+    /// kind of marker. It is loose if blank lines separate its items or the
+    /// blocks directly inside one of them.
+    fn parse_list(&self, lines: &[String]) -> Option<(Block, usize)> {
+        if self.depth.get() == MAX_NESTING {
+            return None;
+        }
+        let mut marker = self.list_marker(&lines[0])?;
+        let (ordered, start, delimiter) = (marker.ordered, marker.start, marker.delimiter);
+        let mut items = Vec::new();
+        let mut loose = false;
+        let mut i = 0;
+        loop {
+            let (item, item_loose, consumed) = self.parse_list_item(&lines[i..], &marker);
+            items.push(item);
+            loose |= item_loose;
+            i += consumed;
+            let blanks = lines[i..]
+                .iter()
+                .take_while(|line| line.trim().is_empty())
+                .count();
+            let next = match lines.get(i + blanks) {
+                Some(line) if !THEMATIC_BREAK.is_match(line) => self.list_marker(line),
+                _ => None,
+            };
+            match next {
+                Some(next) if next.delimiter == delimiter => {
+                    loose |= blanks > 0;
+                    i += blanks;
+                    marker = next;
+                }
+                _ => break,
+            }
+        }
+        let list = List {
+            ordered,
+            start,
+            tight: !loose,
+            items,
+        };
+        Some((Block::List(list), i))
+    }
+    /// Parses a list item, returning it, whether its blocks are separated
+    /// by blank lines and the number of lines used.
+    fn parse_list_item(&self, lines: &[String], marker: &ListMarker) -> (ListItem, bool, usize) {
+        let indent = marker.content_indent;
+        let mut first_line = lines[0].get(indent..).unwrap_or("");
+        let mut checked = None;
+        if let Some(captures) = TASK.captures(first_line) {
+            let rest = &first_line[captures[0].len()..];
+            if !rest.is_empty() {
+                checked = Some(&captures[1] != " ");
+                first_line = rest;
+            }
+        }
+        // An empty item cannot start with a blank line.
+        let item_lines = if marker.empty && lines.get(1).is_some_and(|l| l.trim().is_empty()) {
+            vec![String::new()]
+        } else {
+            self.collect_indented(first_line, &lines[1..], indent)
+        };
+        let consumed = item_lines.len();
+        let (blocks, loose) = self.nested(|| self.parse_block_sequence(&item_lines));
+        (ListItem { checked, blocks }, loose, consumed)
+    }
+    /// Collects the lines of a container block such as a list item, whose
+    /// content starts with `first_line` and continues in `lines`.
+    ///
+    /// Lines indented by at least `indent` belong to it, as do lazy
+    /// paragraph continuation lines and blank lines followed by either.
+    /// The returned lines have the indentation removed and do not include
+    /// trailing blank lines.
+    fn collect_indented(&self, first_line: &str, lines: &[String], indent: usize) -> Vec<String> {
+        let mut collected = vec![first_line.to_string()];
+        let mut blank_run = 0;
+        let mut lazy = LazyContinuation::default();
+        self.track_line(first_line, &mut lazy);
+        for line in lines {
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            if line.trim().is_empty() {
+                blank_run += 1;
+            } else if spaces >= indent
+                || (!self.starts_block(line)
+                    && self.list_marker(line).is_none()
+                    && !FOOTNOTE_DEFINITION.is_match(line)
+                    && self.ends_in_paragraph(&collected, &mut lazy))
+            {
+                blank_run = 0;
+            } else {
+                break;
+            }
+            let content = strip_indent(line, indent);
+            self.track_line(content, &mut lazy);
+            collected.push(content.to_string());
+        }
+        collected.truncate(collected.len() - blank_run);
+        collected
+    }
+    /// Parses a footnote definition such as `[^note]: text`. Following
+    /// lines indented by four spaces belong to the definition.
+    fn parse_footnote_definition(&self, lines: &[String]) -> Option<(Block, usize)> {
+        if self.depth.get() == MAX_NESTING {
+            return None;
+        }
+        let captures = FOOTNOTE_DEFINITION.captures(&lines[0])?;
+        let first_line = &lines[0][captures[0].len()..];
+        let definition_lines = self.collect_indented(first_line, &lines[1..], 4);
+        let definition = Block::FootnoteDefinition {
+            label: captures[1].to_string(),
+            blocks: self.nested(|| self.parse_blocks(&definition_lines)),
+        };
+        Some((definition, definition_lines.len()))
+    }
+    fn list_marker(&self, line: &str) -> Option<ListMarker> {
+        let captures = LIST_ITEM.captures(line)?;
+        let marker = captures.get(2).unwrap();
+        let rest = &line[marker.end()..];
+        let spaces = rest.len() - rest.trim_start_matches(' ').len();
+        let empty = rest.trim().is_empty();
+        // Content starts one space after the marker if the item is blank or
+        // starts with indented code.
+        let content_indent = if empty || spaces > 4 {
+            marker.end() + 1
+        } else {
+            marker.end() + spaces
+        };
+        let number = captures.get(3);
+        Some(ListMarker {
+            delimiter: marker.as_str().chars().last().unwrap(),
+            ordered: number.is_some(),
+            start: number.map_or(1, |n| n.as_str().parse().unwrap()),
+            empty,
+            content_indent,
+        })
+    }
+    /// Parses a GFM pipe table: a header row, a delimiter row giving the
+    /// alignment of each column, then body rows until a blank line or
+    /// another block.
+    fn parse_table(&self, lines: &[String]) -> Option<(Block, usize)> {
+        let alignments = table_alignments(lines.get(1)?)?;
+        if !lines[0].contains('|') {
+            return None;
+        }
+        let header = split_table_row(&lines[0]);
+        if header.len() != alignments.len() {
+            return None;
+        }
+        let mut rows = Vec::new();
+        for line in &lines[2..] {
+            if line.trim().is_empty() || self.starts_block(line) {
+                break;
+            }
+            let mut cells = split_table_row(line);
+            cells.resize(alignments.len(), String::new());
+            rows.push(cells.iter().map(|cell| self.parse_inlines(cell)).collect());
+        }
+        let consumed = rows.len() + 2;
+        let table = Table {
+            alignments,
+            header: header.iter().map(|cell| self.parse_inlines(cell)).collect(),
+            rows,
+        };
+        Some((Block::Table(table), consumed))
+    }
+    /// Parses a paragraph, or a setext heading if its lines are followed by
+    /// a `===` or `---` underline.
+    ///
+    /// Link reference definitions at the start of the paragraph are removed
+    /// and recorded, so no block is returned if nothing else is left.
+    fn parse_paragraph(&self, lines: &[String]) -> (Option<Block>, usize) {
+        // Trailing spaces are kept on all but the last line as they may
+        // make hard line breaks.
+        let mut text = lines[0].trim_start().to_string();
+        let mut consumed = 1;
+        for (i, line) in lines.iter().enumerate().skip(1) {
+            if let Some(captures) = SETEXT_UNDERLINE.captures(line) {
+                let text = self.strip_link_definitions(text.trim_end());
+                if text.is_empty() {
+                    return (None, consumed);
+                }
+                let heading = Block::Heading {
+                    level: if captures[1].starts_with('=') { 1 } else { 2 },
+                    content: self.parse_inlines(text),
+                    id: None,
+                };
+                return (Some(heading), consumed + 1);
+            }
+            if line.trim().is_empty() || self.starts_block(line) {
+                break;
+            }
+            if self.parse_table(&lines[i..]).is_some() {
+                break;
+            }
+            text.push('\n');
+            text.push_str(line.trim_start());
+            consumed += 1;
+        }
+        let text = self.strip_link_definitions(text.trim_end());
+        if text.is_empty() {
+            return (None, consumed);
+        }
+        (Some(Block::Paragraph(self.parse_inlines(text))), consumed)
+    }
+    /// Records the link reference definitions at the start of `text`,
+    /// keeping the first one of each label, and returns the rest.
+    fn strip_link_definitions<'t>(&self, mut text: &'t str) -> &'t str {
+        while let Some((label, reference, consumed)) = parse_link_definition(text) {
+            self.references
+                .borrow_mut()
+                .entry(label)
+                .or_insert(reference);
+            text = &text[consumed..];
+        }
+        text
+    }
+    /// Returns true if `line` starts a list item that may interrupt a
+    /// paragraph: it is not empty and, if ordered, starts at 1.
+    fn interrupts_paragraph(&self, line: &str) -> bool {
+        self.list_marker(line)
+            .is_some_and(|marker| !marker.empty && marker.start == 1)
+    }
+    /// Returns true if `line` opens a block that interrupts a paragraph.
+    fn starts_block(&self, line: &str) -> bool {
+        self.opening_fence(line).is_some()
+            || HEADING.is_match(line)
+            || BLOCKQUOTE.is_match(line)
+            || THEMATIC_BREAK.is_match(line)
+            || self.interrupts_paragraph(line)
+            || html_block_start(line, true).is_some()
+    }
+}
+
+/// Returns true if `block` is a paragraph, or a container whose last block
+/// is an open paragraph.
+fn is_open_paragraph(block: &Block) -> bool {
+    match block {
+        Block::Paragraph(_) => true,
+        Block::BlockQuote(blocks) => blocks.last().is_some_and(is_open_paragraph),
+        Block::List(list) => list
+            .items
+            .last()
+            .and_then(|item| item.blocks.last())
+            .is_some_and(is_open_paragraph),
+        _ => false,
+    }
+}
+
+/// Parses an HTML block, kept as raw lines.
+fn parse_html_block(lines: &[String]) -> Option<(Block, usize)> {
+    let end = html_block_start(&lines[0], false)?;
+    let consumed = match end {
+        HtmlBlockEnd::Text(ends) => {
+            let contains_end = |line: &String| {
+                let line = line.to_ascii_lowercase();
+                ends.iter().any(|end| line.contains(end))
+            };
+            lines
+                .iter()
+                .position(contains_end)
+                .map_or(lines.len(), |i| i + 1)
+        }
+        HtmlBlockEnd::BlankLine => lines
+            .iter()
+            .position(|line| line.trim().is_empty())
+            .unwrap_or(lines.len()),
+    };
+    Some((Block::Html(lines[..consumed].join("\n")), consumed))
+}
+
+/// Returns how the HTML block starting at `line` ends, if it starts one.
+/// Blocks made of a single tag of any other name can't interrupt a
+/// paragraph.
+fn html_block_start(line: &str, interrupting: bool) -> Option<HtmlBlockEnd> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let line = line.trim();
+    let lowercase = line.to_ascii_lowercase();
+    let rest = lowercase.strip_prefix('<')?;
+    let name_end = |rest: &str, name: &str| {
+        rest.strip_prefix(name).is_some_and(|after| {
+            after.is_empty() || after.starts_with([' ', '\t', '>']) || after.starts_with("/>")
+        })
+    };
+    if HTML_RAW_TAGS.iter().any(|tag| name_end(rest, tag)) {
+        return Some(HtmlBlockEnd::Text(&[
+            "</script>",
+            "</pre>",
+            "</style>",
+            "</textarea>",
+        ]));
+    }
+    if rest.starts_with("!--") {
+        return Some(HtmlBlockEnd::Text(&["-->"]));
+    }
+    if rest.starts_with('?') {
+        return Some(HtmlBlockEnd::Text(&["?>"]));
+    }
+    if rest.starts_with("![cdata[") {
+        return Some(HtmlBlockEnd::Text(&["]]>"]));
+    }
+    if rest.starts_with('!') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return Some(HtmlBlockEnd::Text(&[">"]));
+    }
+    let tag = rest.strip_prefix('/').unwrap_or(rest);
+    if HTML_BLOCK_TAGS.iter().any(|name| name_end(tag, name)) {
+        return Some(HtmlBlockEnd::BlankLine);
+    }
+    match parse_markup(line) {
+        Some((Markup::Tag(tag), length))
+            if !interrupting
+                && length == line.len()
+                && !HTML_RAW_TAGS.contains(&tag.name.as_str()) =>
+        {
+            Some(HtmlBlockEnd::BlankLine)
+        }
+        _ => None,
+    }
+}
+
+/// Returns true if `line` closes a code block opened by `fence`: it is made
+/// of at least as many of the same characters.
+fn is_closing_fence(line: &str, fence: &str) -> bool {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let closing = line.trim();
+    let character = fence.chars().next().unwrap();
+    indent < 4 && closing.len() >= fence.len() && closing.chars().all(|c| c == character)
+}
+
+/// Removes the four columns of indentation of a line of an indented code
+/// block, a tab counting as four, or returns `None` if it is indented less
+/// or blank.
+fn strip_code_indent(line: &str) -> Option<&str> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    let mut columns = 0;
+    for (i, c) in line.char_indices() {
+        if columns >= 4 {
+            return Some(&line[i..]);
+        }
+        match c {
+            ' ' => columns += 1,
+            '\t' => columns = 4,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Removes up to `indent` leading spaces from `line`.
+fn strip_indent(line: &str, indent: usize) -> &str {
+    let spaces = line.len() - line.trim_start_matches(' ').len();
+    &line[spaces.min(indent)..]
+}
+
+/// Splits an info string into its language and `key=value` attributes.
+///
+/// Values may be quoted to contain spaces; keys without a value get an
+/// empty one. Keys that are not valid attribute names are skipped.
+fn parse_info_string(info: &str) -> (Option<String>, Vec<(String, String)>) {
+    let mut rest = info.trim();
+    let language_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let language = Some(unescape(&rest[..language_end])).filter(|l| !l.is_empty());
+    rest = rest[language_end..].trim_start();
+    let mut attributes = Vec::new();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = &rest[key_end..];
+        let mut value = "";
+        if let Some(after) = rest.strip_prefix('=') {
+            if let Some(quoted) = after.strip_prefix('"') {
+                let mut escaped = false;
+                let end = quoted
+                    .find(|c| {
+                        let closing = c == '"' && !escaped;
+                        escaped = c == '\\' && !escaped;
+                        closing
+                    })
+                    .unwrap_or(quoted.len());
+                value = &quoted[..end];
+                rest = quoted.get(end + 1..).unwrap_or("");
+            } else {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                value = &after[..end];
+                rest = &after[end..];
+            }
+        }
+        let is_name = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if is_name {
+            attributes.push((key.to_ascii_lowercase(), unescape(value)));
+        }
+        rest = rest.trim_start();
+    }
+    (language, attributes)
+}
+
+/// Returns the column alignments of a table delimiter row like `|:--|--:|`.
+fn table_alignments(line: &str) -> Option<Vec<Alignment>> {
+    if !line.contains('|') {
+        return None;
+    }
+    split_table_row(line)
+        .iter()
+        .map(|cell| {
+            let left = cell.starts_with(':');
+            let right = cell.ends_with(':');
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (left, right) {
+                (true, true) => Alignment::Center,
+                (true, false) => Alignment::Left,
+                (false, true) => Alignment::Right,
+                (false, false) => Alignment::None,
+            })
+        })
+        .collect()
+}
+
+/// Splits a table row into trimmed cells at unescaped pipes, dropping the
+/// optional leading and trailing pipe. Escaped pipes become plain pipes.
+fn split_table_row(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let mut cells = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                cells.last_mut().unwrap().push('|');
+                chars.next();
+            }
+            '|' => cells.push(String::new()),
+            _ => cells.last_mut().unwrap().push(c),
+        }
+    }
+    if line.starts_with('|') {
+        cells.remove(0);
+    }
+    if cells.len() > 1 && cells.last().is_some_and(|cell| cell.trim().is_empty()) {
+        cells.pop();
+    }
+    cells.iter().map(|cell| cell.trim().to_string()).collect()
+}
+
+/// Removes the optional closing `#` sequence of an ATX heading, as in
+/// `## Title ##`. The sequence must be preceded by a space.
+fn strip_closing_sequence(text: &str) -> &str {
+    let text = text.trim();
+    let without_hashes = text.trim_end_matches('#');
+    if without_hashes.is_empty() {
+        return "";
+    }
+    if without_hashes.ends_with([' ', '\t']) {
+        return without_hashes.trim_end();
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Inline;
+
+    fn parse(markdown: &str) -> Vec<Block> {
+        Lexer::new().parse(markdown).blocks
+    }
+
+    fn paragraph(text: &str) -> Block {
+        Block::Paragraph(vec![Inline::Text(text.to_string())])
+    }
+
+    #[test]
+    fn blockquote_lazy_line_continues_paragraph() {
+        assert_eq!(
+            parse("> a\nb"),
+            vec![Block::BlockQuote(vec![Block::Paragraph(vec![
+                Inline::Text(String::from("a")),
+                Inline::SoftBreak,
+                Inline::Text(String::from("b")),
+            ])])]
+        );
+    }
+
+    #[test]
+    fn blockquote_lazy_line_does_not_continue_other_blocks() {
+        let blocks = parse("> ```\n> code\nfoo");
+        assert!(matches!(&blocks[0], Block::BlockQuote(quote)
+            if matches!(&quote[0], Block::CodeBlock(code) if code.literal == "code\n")));
+        assert_eq!(blocks[1], paragraph("foo"));
+        let blocks = parse("> # h\nfoo");
+        assert!(matches!(&blocks[0], Block::BlockQuote(quote) if quote.len() == 1));
+        assert_eq!(blocks[1], paragraph("foo"));
+    }
+
+    #[test]
+    fn blockquote_lazy_lines_continue_nested_paragraphs() {
+        let blocks = parse("> - a\nb\n> - c\nd\n> e\nf");
+        let [Block::BlockQuote(quote)] = &blocks[..] else {
+            panic!("expected one blockquote, got {blocks:?}");
+        };
+        let [Block::List(list)] = &quote[..] else {
+            panic!("expected a list in the quote, got {quote:?}");
+        };
+        assert_eq!(list.items.len(), 2);
+        assert!(
+            matches!(&list.items[1].blocks[..], [Block::Paragraph(content)] if content.len() == 7)
+        );
+    }
+
+    #[test]
+    fn blockquote_nesting_is_capped() {
+        let mut blocks = parse(&format!("{} a", ">".repeat(10_000)));
+        let mut depth = 0;
+        while let [Block::BlockQuote(quote)] = &blocks[..] {
+            blocks = quote.clone();
+            depth += 1;
+        }
+        assert_eq!(depth, MAX_NESTING);
+        assert!(matches!(&blocks[..], [Block::Paragraph(content)]
+            if matches!(&content[0], Inline::Text(text) if text.starts_with('>'))));
+    }
+
+    #[test]
+    fn only_ascii_digits_number_list_items() {
+        assert_eq!(parse("١. foo"), vec![paragraph("١. foo")]);
+    }
+
+    #[test]
+    fn list_nesting_is_capped() {
+        let markdown: String = (0..100)
+            .map(|depth| format!("{}- a\n", "  ".repeat(depth)))
+            .collect();
+        let mut blocks = parse(&markdown);
+        let mut depth = 0;
+        while let [Block::List(list)] = &blocks[..] {
+            blocks = list.items[0].blocks.last().into_iter().cloned().collect();
+            depth += 1;
+        }
+        assert_eq!(depth, MAX_NESTING);
+    }
+
+    #[test]
+    fn list_item_lazy_line_does_not_continue_code() {
+        let blocks = parse("- ```\n  code\nfoo");
+        assert!(matches!(&blocks[0], Block::List(list)
+            if matches!(&list.items[0].blocks[..], [Block::CodeBlock(code)] if code.literal == "code\n")));
+        assert_eq!(blocks[1], paragraph("foo"));
+        let blocks = parse("- a\nb");
+        assert!(matches!(&blocks[..], [Block::List(list)] if list.items[0].blocks.len() == 1));
+    }
+
+    #[test]
+    fn parses_atx_and_setext_headings() {
+        let heading = |level, text: &str| Block::Heading {
+            level,
+            content: vec![Inline::Text(text.to_string())],
+            id: None,
+        };
+        assert_eq!(
+            parse("# a #\n\nb\n===\n\nc\n---\n####### d"),
+            vec![
+                heading(1, "a"),
+                heading(1, "b"),
+                heading(2, "c"),
+                paragraph("####### d")
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_lists() {
+        let blocks = parse("3. a\n4. b\n\n- [x] c\n\n- d");
+        let [Block::List(ordered), Block::List(tasks)] = &blocks[..] else {
+            panic!("expected two lists, got {blocks:?}");
+        };
+        assert!(ordered.ordered && ordered.tight);
+        assert_eq!(ordered.start, 3);
+        assert_eq!(ordered.items.len(), 2);
+        assert!(!tasks.ordered && !tasks.tight);
+        assert_eq!(tasks.items[0].checked, Some(true));
+        assert_eq!(tasks.items[1].checked, None);
+    }
+
+    #[test]
+    fn parses_thematic_breaks_and_html_blocks() {
+        assert_eq!(
+            parse("* * *\n\n<div>\n*a*\n</div>"),
+            vec![
+                Block::ThematicBreak,
+                Block::Html(String::from("<div>\n*a*\n</div>"))
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_tables() {
+        let blocks = parse("| a | b |\n|:--|--:|\n| 1 | 2 |");
+        let [Block::Table(table)] = &blocks[..] else {
+            panic!("expected a table, got {blocks:?}");
+        };
+        assert_eq!(table.alignments, vec![Alignment::Left, Alignment::Right]);
+        assert_eq!(table.header.len(), 2);
+        assert_eq!(
+            table.rows,
+            vec![vec![
+                vec![Inline::Text(String::from("1"))],
+                vec![Inline::Text(String::from("2"))],
+            ]]
+        );
+    }
+
+    #[test]
+    fn parses_footnote_definitions() {
+        let blocks = parse("a\n\n[^n]: note\n    more");
+        assert!(
+            matches!(&blocks[1], Block::FootnoteDefinition { label, blocks }
+            if label == "n" && blocks.len() == 1)
+        );
+    }
+
+    #[test]
+    fn parses_fenced_and_indented_code() {
+        let blocks = parse("```rust title=\"x\"\nfn f() {}\n```\n\n    indented\n\n~~~\nopen");
+        let [Block::CodeBlock(fenced), Block::CodeBlock(indented), Block::CodeBlock(open)] =
+            &blocks[..]
+        else {
+            panic!("expected three code blocks, got {blocks:?}");
+        };
+        assert_eq!(fenced.language.as_deref(), Some("rust"));
+        assert_eq!(
+            fenced.attributes,
+            vec![(String::from("title"), String::from("x"))]
+        );
+        assert_eq!(fenced.literal, "fn f() {}\n");
+        assert_eq!(indented.info, "");
+        assert_eq!(indented.literal, "indented\n");
+        // An unclosed fence runs to the end of the document.
+        assert_eq!(open.literal, "open\n");
+    }
+
+    #[test]
+    fn resolves_link_reference_definitions() {
+        let blocks = parse("[a]\n\n[A]: /url \"title\"");
+        assert_eq!(
+            blocks,
+            vec![Block::Paragraph(vec![Inline::Link {
+                url: String::from("/url"),
+                title: Some(String::from("title")),
+                children: vec![Inline::Text(String::from("a"))],
+            }])]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! rsmdc is a Rust Markdown to HTML converter.
+//!
+//! ```
+//! let html = rsmdc::to_html("# Hello", &rsmdc::Options::default());
+//! assert!(html.contains("<h1 id=\"hello\">Hello</h1>"));
+//! ```
+
+pub mod ast;
+mod builder;
+mod entity;
+pub mod highlight;
+mod html;
+mod inline;
+mod lexer;
+mod options;
+pub mod sanitize;
+pub mod slug;
+
+pub use ast::{Block, Document, Task};
+pub use builder::Builder;
+pub use highlight::Theme;
+pub use html::{HTMLElemnt, HTMLElemntList, Renderer};
+pub use lexer::Lexer;
+pub use options::Options;
+pub use sanitize::{HtmlMode, Sanitizer};
+pub use slug::SlugStyle;
+
+/// Parses Markdown into a [`Document`] tree.
+pub fn parse(markdown: &str) -> Document {
+    Lexer::new().parse(markdown)
+}
+
+/// Converts Markdown to an HTML fragment, without the surrounding document.
+pub fn to_html(markdown: &str, options: &Options) -> String {
+    let mut document = Lexer::new().autolink(options.autolink).parse(markdown);
+    if options.heading_ids {
+        document.assign_heading_ids(options.slug_style);
+    }
+    if options.toc_at_top {
+        document
+            .blocks
+            .insert(0, Block::TableOfContents(Vec::new()));
+    }
+    document.fill_tables_of_contents(options.toc_min_depth, options.toc_max_depth);
+    Renderer::new(options).render(&document)
+}
+
+/// Converts Markdown to a complete HTML document built with [`Builder::build`].
+pub fn to_html_document(markdown: &str, options: &Options) -> String {
+    let mut builder = Builder::new(to_html(markdown, options)).title(&options.title);
+    if let Some(theme) = options.theme {
+        builder = builder.style(theme.css());
+    }
+    builder.build()
+}
@@ -0,0 +1,68 @@
+use crate::highlight::Theme;
+use crate::sanitize::{HtmlMode, Sanitizer};
+use crate::slug::SlugStyle;
+
+/// Settings that control how Markdown is converted to HTML.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Title used for the `<title>` of full documents.
+    pub title: String,
+    /// Highlight fenced code blocks in supported languages on the server.
+    pub highlight: bool,
+    /// Stylesheet embedded in full documents for highlighted code.
+    pub theme: Option<Theme>,
+    /// Give headings an `id` so they can be linked to.
+    pub heading_ids: bool,
+    /// The algorithm used to generate heading IDs.
+    pub slug_style: SlugStyle,
+    /// Insert an `<a class="anchor">` permalink into headings with an ID.
+    pub heading_anchors: bool,
+    /// Insert a table of contents at the top of the document, in addition
+    /// to any `[[TOC]]` placeholder.
+    pub toc_at_top: bool,
+    /// The shallowest heading level listed in tables of contents.
+    pub toc_min_depth: u8,
+    /// The deepest heading level listed in tables of contents.
+    pub toc_max_depth: u8,
+    /// Turn bare URLs and email addresses into links.
+    pub autolink: bool,
+    /// Leave out link and image URLs with a scheme missing from
+    /// `allowed_schemes`, such as `javascript:`, and sanitize raw HTML
+    /// unless it is escaped.
+    pub safe: bool,
+    /// The URL schemes allowed in safe mode. Images may also use `data:`
+    /// URLs of raster images.
+    pub allowed_schemes: Vec<String>,
+    /// How raw HTML is rendered. Passing it through is turned into
+    /// sanitizing in safe mode.
+    pub html: HtmlMode,
+    /// The tags and attributes kept when sanitizing raw HTML.
+    pub sanitizer: Sanitizer,
+    /// Render every line ending in paragraphs as `<br>`, as in GitHub
+    /// comments.
+    pub hard_breaks: bool,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            title: String::from("Markdown"),
+            highlight: false,
+            theme: None,
+            heading_ids: true,
+            slug_style: SlugStyle::GitHub,
+            heading_anchors: false,
+            toc_at_top: false,
+            toc_min_depth: 1,
+            toc_max_depth: 6,
+            autolink: false,
+            safe: false,
+            allowed_schemes: ["http", "https", "mailto", "ftp", "tel"]
+                .map(String::from)
+                .to_vec(),
+            html: HtmlMode::PassThrough,
+            sanitizer: Sanitizer::default(),
+            hard_breaks: false,
+        }
+    }
+}
@@ -0,0 +1,556 @@
+//! Raw HTML in documents, and protection against scripts smuggled into it.
+
+use crate::entity::decode_entity;
+use crate::html::escape_html;
+use std::collections::{HashMap, HashSet};
+
+/// The types of `data:` URLs that may be used as image sources.
+const IMAGE_DATA_TYPES: [&str; 5] = [
+    "image/png",
+    "image/gif",
+    "image/jpeg",
+    "image/jpg",
+    "image/webp",
+];
+
+/// Attributes holding a URL, checked with [`is_safe_url`].
+const URL_ATTRIBUTES: [&str; 6] = ["href", "src", "cite", "action", "formaction", "poster"];
+
+/// Elements that have no closing tag.
+const VOID_TAGS: [&str; 6] = ["br", "hr", "img", "input", "col", "wbr"];
+
+/// Elements whose content is dropped along with them when they are not
+/// allowed.
+const RAW_TEXT_TAGS: [&str; 6] = ["script", "style", "textarea", "iframe", "noscript", "title"];
+
+/// The tags allowed by [`Sanitizer::default`] and their attributes.
+const DEFAULT_TAGS: [(&str, &[&str]); 49] = [
+    ("a", &["href", "title"]),
+    ("abbr", &["title"]),
+    ("b", &[]),
+    ("blockquote", &["cite"]),
+    ("br", &[]),
+    ("caption", &[]),
+    ("cite", &[]),
+    ("code", &[]),
+    ("dd", &[]),
+    ("del", &[]),
+    ("details", &["open"]),
+    ("div", &[]),
+    ("dl", &[]),
+    ("dt", &[]),
+    ("em", &[]),
+    ("figcaption", &[]),
+    ("figure", &[]),
+    ("h1", &[]),
+    ("h2", &[]),
+    ("h3", &[]),
+    ("h4", &[]),
+    ("h5", &[]),
+    ("h6", &[]),
+    ("hr", &[]),
+    ("i", &[]),
+    ("img", &["src", "alt", "title", "width", "height"]),
+    ("ins", &[]),
+    ("kbd", &[]),
+    ("li", &[]),
+    ("mark", &[]),
+    ("ol", &["start"]),
+    ("p", &[]),
+    ("pre", &[]),
+    ("q", &["cite"]),
+    ("s", &[]),
+    ("samp", &[]),
+    ("small", &[]),
+    ("span", &[]),
+    ("strong", &[]),
+    ("sub", &[]),
+    ("summary", &[]),
+    ("sup", &[]),
+    ("table", &[]),
+    ("tbody", &[]),
+    ("td", &["align", "colspan", "rowspan"]),
+    ("th", &["align", "colspan", "rowspan"]),
+    ("thead", &[]),
+    ("tr", &[]),
+    ("ul", &[]),
+];
+
+/// How raw HTML in the Markdown is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HtmlMode {
+    /// Written to the output as is.
+    PassThrough,
+    /// Shown as text.
+    Escape,
+    /// Cleaned up by the [`Sanitizer`] of the options.
+    Sanitize,
+}
+
+impl HtmlMode {
+    /// Returns the mode matching `name` (`pass-through`, `escape` or
+    /// `sanitize`).
+    pub fn from_name(name: &str) -> Option<HtmlMode> {
+        match name {
+            "pass-through" => Some(HtmlMode::PassThrough),
+            "escape" => Some(HtmlMode::Escape),
+            "sanitize" => Some(HtmlMode::Sanitize),
+            _ => None,
+        }
+    }
+}
+
+/// Removes the tags and attributes of raw HTML that are not allowed.
+///
+/// Comments, event handlers such as `onclick` and URLs with a scheme that
+/// is not allowed are always removed. The content of removed tags is kept,
+/// except for elements such as `<script>` and `<style>`. Closing tags
+/// without a matching opening tag are removed too, so that the HTML can't
+/// close the elements of the page it is embedded in.
+#[derive(Debug, Clone)]
+pub struct Sanitizer {
+    /// The allowed tags and their allowed attributes.
+    tags: HashMap<String, HashSet<String>>,
+}
+
+impl Default for Sanitizer {
+    /// Allows common formatting tags such as `<details>`, `<kbd>` and
+    /// `<sup>`.
+    fn default() -> Sanitizer {
+        DEFAULT_TAGS
+            .iter()
+            .fold(Sanitizer::new(), |sanitizer, (tag, attributes)| {
+                sanitizer.allow_tag(tag, attributes)
+            })
+    }
+}
+
+impl Sanitizer {
+    /// Creates a sanitizer that allows no tags.
+    pub fn new() -> Sanitizer {
+        Sanitizer {
+            tags: HashMap::new(),
+        }
+    }
+    /// Allows `tag` with `attributes`, in addition to those already allowed.
+    pub fn allow_tag(mut self, tag: &str, attributes: &[&str]) -> Sanitizer {
+        self.tags
+            .entry(tag.to_ascii_lowercase())
+            .or_default()
+            .extend(attributes.iter().map(|name| name.to_ascii_lowercase()));
+        self
+    }
+    /// Stops allowing `tag`.
+    pub fn deny_tag(mut self, tag: &str) -> Sanitizer {
+        self.tags.remove(&tag.to_ascii_lowercase());
+        self
+    }
+    /// Sanitizes the raw HTML `html`, keeping URLs whose scheme is in
+    /// `allowed_schemes`. Elements left open are closed at the end.
+    pub fn sanitize(&self, html: &str, allowed_schemes: &[String]) -> String {
+        let mut open = Vec::new();
+        let mut sanitized = self.sanitize_fragment(html, allowed_schemes, &mut open);
+        sanitized.push_str(&close_elements(&mut open));
+        sanitized
+    }
+    /// Sanitizes a fragment of a document, `open` holding the names of the
+    /// elements opened by the previous fragments and not closed yet.
+    pub(crate) fn sanitize_fragment(
+        &self,
+        html: &str,
+        allowed_schemes: &[String],
+        open: &mut Vec<String>,
+    ) -> String {
+        let mut sanitized = String::with_capacity(html.len());
+        let mut i = 0;
+        while i < html.len() {
+            let rest = &html[i..];
+            if let Some((markup, length)) = parse_markup(rest) {
+                i += length;
+                let Markup::Tag(tag) = markup else {
+                    continue;
+                };
+                if !self.tags.contains_key(&tag.name) {
+                    if !tag.closing && RAW_TEXT_TAGS.contains(&tag.name.as_str()) {
+                        i += raw_text_length(&html[i..], &tag.name);
+                    }
+                } else if !tag.closing {
+                    if !VOID_TAGS.contains(&tag.name.as_str()) {
+                        open.push(tag.name.clone());
+                    }
+                    sanitized.push_str(&self.render_tag(&tag, allowed_schemes));
+                } else if let Some(index) = open.iter().rposition(|name| *name == tag.name) {
+                    // Elements opened inside this one are closed with it.
+                    sanitized.push_str(&close_elements(&mut open.split_off(index)));
+                }
+                continue;
+            }
+            let c = rest.chars().next().unwrap();
+            match c {
+                '<' => sanitized.push_str("&lt;"),
+                '>' => sanitized.push_str("&gt;"),
+                '&' if decode_entity(rest).is_none() => sanitized.push_str("&amp;"),
+                _ => sanitized.push(c),
+            }
+            i += c.len_utf8();
+        }
+        sanitized
+    }
+    /// Renders an allowed opening tag with its allowed attributes.
+    fn render_tag(&self, tag: &Tag, allowed_schemes: &[String]) -> String {
+        let allowed = &self.tags[&tag.name];
+        let mut html = format!("<{}", tag.name);
+        for (name, value) in &tag.attributes {
+            if !allowed.contains(name) || name.starts_with("on") {
+                continue;
+            }
+            let Some(value) = value else {
+                html.push_str(&format!(" {}", name));
+                continue;
+            };
+            let value = decode_entities(value);
+            let image = tag.name == "img";
+            if URL_ATTRIBUTES.contains(&name.as_str())
+                && !is_safe_url(&value, allowed_schemes, image)
+            {
+                continue;
+            }
+            html.push_str(&format!(" {}=\"{}\"", name, escape_html(&value)));
+        }
+        if tag.self_closing {
+            html.push_str(" /");
+        }
+        html.push('>');
+        html
+    }
+}
+
+/// Closes the elements of `open`, innermost first, and empties it.
+pub(crate) fn close_elements(open: &mut Vec<String>) -> String {
+    open.drain(..)
+        .rev()
+        .map(|name| format!("</{}>", name))
+        .collect()
+}
+
+/// Returns true if `url` is relative, uses a scheme of `allowed_schemes`
+/// or, for an image, is a `data:` URL of a raster image.
+pub fn is_safe_url(url: &str, allowed_schemes: &[String], image: bool) -> bool {
+    // Browsers ignore whitespace and control characters in schemes, as in
+    // `java\tscript:`.
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let Some(scheme) = scheme(&url) else {
+        return true;
+    };
+    if image && scheme == "data" {
+        let media_type = url["data:".len()..].split([';', ',']).next().unwrap();
+        return IMAGE_DATA_TYPES.contains(&media_type);
+    }
+    allowed_schemes
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+}
+
+/// Returns the scheme of an absolute URL.
+fn scheme(url: &str) -> Option<&str> {
+    let end = url.find([':', '/', '?', '#'])?;
+    let scheme = &url[..end];
+    let is_scheme = url[end..].starts_with(':')
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c));
+    is_scheme.then_some(scheme)
+}
+
+/// A piece of raw HTML markup.
+pub(crate) enum Markup {
+    Tag(Tag),
+    /// A comment, processing instruction, declaration or CDATA section.
+    Other,
+}
+
+/// An opening or closing HTML tag.
+pub(crate) struct Tag {
+    /// The lowercase name of the tag.
+    pub name: String,
+    pub closing: bool,
+    /// The attributes with their lowercase names and raw values.
+    pub attributes: Vec<(String, Option<String>)>,
+    pub self_closing: bool,
+}
+
+/// Parses the HTML tag, comment, processing instruction, declaration or
+/// CDATA section at the start of `text`, returning it with its length.
+pub(crate) fn parse_markup(text: &str) -> Option<(Markup, usize)> {
+    let rest = text.strip_prefix('<')?;
+    let delimited = |open: &str, close: &str| {
+        let content = rest.strip_prefix(open)?;
+        let end = content.find(close)?;
+        Some((Markup::Other, 1 + open.len() + end + close.len()))
+    };
+    if let Some(comment) = rest.strip_prefix("!--") {
+        // `<!-->` and `<!--->` are complete, empty comments.
+        if let Some(after) = comment.strip_prefix('>').or(comment.strip_prefix("->")) {
+            return Some((Markup::Other, text.len() - after.len()));
+        }
+        return delimited("!--", "-->");
+    }
+    if rest.starts_with("![CDATA[") {
+        return delimited("![CDATA[", "]]>");
+    }
+    if rest.starts_with('?') {
+        return delimited("?", "?>");
+    }
+    if let Some(declaration) = rest.strip_prefix('!') {
+        if !declaration.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        return delimited("!", ">");
+    }
+    parse_tag(text).map(|(tag, length)| (Markup::Tag(tag), length))
+}
+
+fn parse_tag(text: &str) -> Option<(Tag, usize)> {
+    let rest = text.strip_prefix('<')?;
+    let (closing, rest) = match rest.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let name_length = tag_name_length(rest)?;
+    let name = rest[..name_length].to_ascii_lowercase();
+    let mut rest = &rest[name_length..];
+    let mut attributes = Vec::new();
+    if !closing {
+        while let Some((attribute, after)) = parse_attribute(rest) {
+            attributes.push(attribute);
+            rest = after;
+        }
+    }
+    rest = rest.trim_start();
+    let self_closing = !closing && rest.starts_with("/>");
+    if self_closing {
+        rest = &rest[1..];
+    }
+    let rest = rest.strip_prefix('>')?;
+    let tag = Tag {
+        name,
+        closing,
+        attributes,
+        self_closing,
+    };
+    Some((tag, text.len() - rest.len()))
+}
+
+/// Returns the length of the tag name at the start of `text`.
+fn tag_name_length(text: &str) -> Option<usize> {
+    if !text.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    Some(
+        text.find(|c: char| !c.is_ascii_alphanumeric() && c != '-')
+            .unwrap_or(text.len()),
+    )
+}
+
+/// Parses an attribute, with the whitespace before it, at the start of
+/// `text`.
+fn parse_attribute(text: &str) -> Option<((String, Option<String>), &str)> {
+    let rest = text.trim_start();
+    if rest.len() == text.len() {
+        return None;
+    }
+    if !rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == ':') {
+        return None;
+    }
+    let name_end = rest
+        .find(|c: char| !c.is_ascii_alphanumeric() && !"_.:-".contains(c))
+        .unwrap_or(rest.len());
+    let name = rest[..name_end].to_ascii_lowercase();
+    let after_name = &rest[name_end..];
+    let Some(value) = after_name.trim_start().strip_prefix('=') else {
+        return Some(((name, None), after_name));
+    };
+    let value = value.trim_start();
+    let (value, rest) = match value.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = value[1..].find(quote)?;
+            (&value[1..end + 1], &value[end + 2..])
+        }
+        _ => {
+            let end = value
+                .find(|c: char| c.is_whitespace() || "\"'=<>`".contains(c))
+                .unwrap_or(value.len());
+            if end == 0 {
+                return None;
+            }
+            (&value[..end], &value[end..])
+        }
+    };
+    Some(((name, Some(value.to_string())), rest))
+}
+
+/// Returns the length of the content of the raw text element `name` and of
+/// its closing tag, or of the rest of `html` if it isn't closed.
+fn raw_text_length(html: &str, name: &str) -> usize {
+    let closing = format!("</{}", name);
+    let start = html
+        .match_indices("</")
+        .map(|(start, _)| start)
+        .find(|&start| {
+            html.as_bytes()[start..]
+                .get(..closing.len())
+                .is_some_and(|tag| tag.eq_ignore_ascii_case(closing.as_bytes()))
+        });
+    match start {
+        Some(start) => match html[start..].find('>') {
+            Some(end) => start + end + 1,
+            None => html.len(),
+        },
+        None => html.len(),
+    }
+}
+
+/// Decodes the entities of an attribute value.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < text.len() {
+        if let Some((entity, length)) = decode_entity(&text[i..]) {
+            decoded.push_str(&entity);
+            i += length;
+        } else {
+            let c = text[i..].chars().next().unwrap();
+            decoded.push(c);
+            i += c.len_utf8();
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schemes() -> Vec<String> {
+        ["http", "https", "mailto"].map(String::from).to_vec()
+    }
+
+    fn sanitize(html: &str) -> String {
+        Sanitizer::default().sanitize(html, &schemes())
+    }
+
+    #[test]
+    fn rejects_script_urls() {
+        assert!(!is_safe_url("javascript:alert(1)", &schemes(), false));
+        assert!(!is_safe_url("JaVaScRiPt:alert(1)", &schemes(), false));
+        assert!(!is_safe_url(" java\tscript:alert(1)", &schemes(), false));
+        assert!(!is_safe_url("vbscript:msgbox(1)", &schemes(), false));
+        assert!(!is_safe_url("data:text/html,<script>", &schemes(), true));
+        assert!(!is_safe_url("data:image/svg+xml,<svg>", &schemes(), true));
+    }
+
+    #[test]
+    fn accepts_allowed_and_relative_urls() {
+        assert!(is_safe_url("https://example.com", &schemes(), false));
+        assert!(is_safe_url("/path/to:page", &schemes(), false));
+        assert!(is_safe_url("#section", &schemes(), false));
+        assert!(is_safe_url("data:image/png;base64,iVBOR", &schemes(), true));
+        assert!(!is_safe_url(
+            "data:image/png;base64,iVBOR",
+            &schemes(),
+            false
+        ));
+    }
+
+    #[test]
+    fn removes_unsafe_urls_from_attributes() {
+        assert_eq!(
+            sanitize("<a href=\"javascript:alert(1)\">x</a>"),
+            "<a>x</a>"
+        );
+        assert_eq!(
+            sanitize("<a href=\"&#106;avascript:alert(1)\">x</a>"),
+            "<a>x</a>"
+        );
+        assert_eq!(
+            sanitize("<a href=\"jav&#x09;ascript:alert(1)\">x</a>"),
+            "<a>x</a>"
+        );
+        assert_eq!(
+            sanitize("<a href='https://example.com' title=t>x</a>"),
+            "<a href=\"https://example.com\" title=\"t\">x</a>"
+        );
+    }
+
+    #[test]
+    fn removes_event_handlers_and_unknown_attributes() {
+        assert_eq!(sanitize("<img src=x onerror=alert(1)>"), "<img src=\"x\">");
+        assert_eq!(
+            sanitize("<span style=\"color:red\" onmouseover=\"x()\">a</span>"),
+            "<span>a</span>"
+        );
+        let sanitizer = Sanitizer::new().allow_tag("div", &["onclick"]);
+        assert_eq!(
+            sanitizer.sanitize("<div onclick=\"x()\">a</div>", &schemes()),
+            "<div>a</div>"
+        );
+    }
+
+    #[test]
+    fn drops_raw_text_elements_with_their_content() {
+        assert_eq!(sanitize("a<script>alert(1)</script>b"), "ab");
+        assert_eq!(sanitize("a<STYLE>p{}</style >b"), "ab");
+        assert_eq!(sanitize("a<script>never closed"), "a");
+        assert_eq!(sanitize("a<script>x</ScRiPt>b"), "ab");
+        assert_eq!(sanitize("<x-custom>kept</x-custom>"), "kept");
+    }
+
+    #[test]
+    fn strips_comments_and_declarations() {
+        assert_eq!(sanitize("a<!-- <script>alert(1)</script> -->b"), "ab");
+        assert_eq!(sanitize("<!DOCTYPE html><?php echo 1 ?>x"), "x");
+        assert_eq!(sanitize("<![CDATA[<b>]]>x"), "x");
+    }
+
+    #[test]
+    fn escapes_stray_markup() {
+        assert_eq!(sanitize("1 < 2 & 3 > 2"), "1 &lt; 2 &amp; 3 &gt; 2");
+        assert_eq!(sanitize("&copy; &amp;"), "&copy; &amp;");
+    }
+
+    #[test]
+    fn balances_tags() {
+        assert_eq!(sanitize("<div>x</div></div></div>"), "<div>x</div>");
+        assert_eq!(sanitize("</li></table>x"), "x");
+        assert_eq!(sanitize("<div><b>x</div>"), "<div><b>x</b></div>");
+        assert_eq!(
+            sanitize("<details><summary>s"),
+            "<details><summary>s</summary></details>"
+        );
+        assert_eq!(sanitize("a<br>b<hr/>"), "a<br>b<hr />");
+    }
+
+    #[test]
+    fn balances_tags_across_fragments() {
+        let sanitizer = Sanitizer::default();
+        let mut open = Vec::new();
+        assert_eq!(
+            sanitizer.sanitize_fragment("<kbd>", &schemes(), &mut open),
+            "<kbd>"
+        );
+        assert_eq!(
+            sanitizer.sanitize_fragment("</kbd>", &schemes(), &mut open),
+            "</kbd>"
+        );
+        assert_eq!(
+            sanitizer.sanitize_fragment("</kbd>", &schemes(), &mut open),
+            ""
+        );
+        assert!(open.is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+//! Heading IDs for deep links into a document.
+
+use std::collections::HashSet;
+
+/// The algorithm used to turn heading text into an ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlugStyle {
+    /// The IDs GitHub generates: lowercase, punctuation removed, spaces
+    /// turned into `-`, non-ASCII letters kept.
+    GitHub,
+    /// Only lowercase ASCII letters and digits, separated by single `-`.
+    Ascii,
+}
+
+impl SlugStyle {
+    /// Returns the style matching `name` (`github` or `ascii`).
+    pub fn from_name(name: &str) -> Option<SlugStyle> {
+        match name {
+            "github" => Some(SlugStyle::GitHub),
+            "ascii" => Some(SlugStyle::Ascii),
+            _ => None,
+        }
+    }
+}
+
+/// Turns `text` into a slug without deduplication.
+pub fn slugify(text: &str, style: SlugStyle) -> String {
+    match style {
+        SlugStyle::GitHub => text
+            .trim()
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                ' ' => Some('-'),
+                '-' | '_' => Some(c),
+                _ if c.is_alphanumeric() => Some(c),
+                _ => None,
+            })
+            .collect(),
+        SlugStyle::Ascii => text
+            .to_ascii_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<&str>>()
+            .join("-"),
+    }
+}
+
+/// Generates unique slugs, suffixing repeated ones with `-1`, `-2`, ...
+pub struct Slugger {
+    style: SlugStyle,
+    seen: HashSet<String>,
+}
+
+impl Slugger {
+    pub fn new(style: SlugStyle) -> Slugger {
+        Slugger {
+            style,
+            seen: HashSet::new(),
+        }
+    }
+    /// Returns the slug of `text`, empty for a heading without letters or
+    /// digits the first time, as GitHub does.
+    pub fn slug(&mut self, text: &str) -> String {
+        let base = slugify(text, self.style);
+        let mut slug = base.clone();
+        let mut count = 0;
+        while self.seen.contains(&slug) {
+            count += 1;
+            slug = format!("{}-{}", base, count);
+        }
+        self.seen.insert(slug.clone());
+        slug
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deduplicates_slugs() {
+        let mut slugger = Slugger::new(SlugStyle::GitHub);
+        assert_eq!(slugger.slug("Intro"), "intro");
+        assert_eq!(slugger.slug("Intro"), "intro-1");
+        assert_eq!(slugger.slug("intro"), "intro-2");
+        assert_eq!(slugger.slug("Intro 1"), "intro-1-1");
+        assert_eq!(slugger.slug("?"), "");
+        assert_eq!(slugger.slug(""), "-1");
+    }
+
+    #[test]
+    fn slugifies_in_both_styles() {
+        let text = "Hello, World! Ünïcode_2.0";
+        assert_eq!(slugify(text, SlugStyle::GitHub), "hello-world-ünïcode_20");
+        assert_eq!(slugify(text, SlugStyle::Ascii), "hello-world-n-code-2-0");
+    }
+}